@@ -0,0 +1,611 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Collurgy, CollurgyError};
+
+/// Formats written through a serializer instead of a `{TOKEN}` template,
+/// so nesting and the theme name are always escaped correctly
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Builder {
+    Alacritty,
+    Helix,
+    VSCode,
+    TmTheme,
+    Emacs,
+}
+
+/// Where a builder takes a color from
+#[derive(Clone, Copy)]
+enum Slot {
+    Index(usize),
+    Extra(&'static str),
+    Accent,
+}
+
+/// A TOML table of string values
+fn strings<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> toml::Value {
+    toml::Value::Table(
+        pairs
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), toml::Value::String(v.to_string())))
+            .collect(),
+    )
+}
+
+/// Helix syntax scopes and the extras id coloring each
+const HELIX_SCOPES: [(&str, &str); 28] = [
+    ("attribute", "TYPE"),
+    ("type", "TYPE"),
+    ("type.builtin", "TYPE"),
+    ("constructor", "TYPE"),
+    ("constant", "CONSTANT"),
+    ("constant.numeric", "CONSTANT"),
+    ("string", "STRING"),
+    ("comment", "COMMENT"),
+    ("variable", "VARIABLE"),
+    ("variable.parameter", "VARIABLE"),
+    ("label", "SPECIAL"),
+    ("punctuation", "PUNCTUATION"),
+    ("keyword", "KEYWORD"),
+    ("operator", "OPERATOR"),
+    ("function", "FUNCTION"),
+    ("function.macro", "SPECIAL"),
+    ("tag", "KEYWORD"),
+    ("namespace", "TYPE"),
+    ("special", "SPECIAL"),
+    ("markup.heading", "KEYWORD"),
+    ("markup.link.url", "STRING"),
+    ("diff.plus", "DIFF_PLUS"),
+    ("diff.minus", "DIFF_MINUS"),
+    ("diff.delta", "DIFF_DELTA"),
+    ("error", "ERROR"),
+    ("warning", "WARNING"),
+    ("info", "INFO"),
+    ("hint", "HINT"),
+];
+
+/// Helix UI scopes as (scope, fg, bg) palette names
+const HELIX_UI: [(&str, Option<&str>, Option<&str>); 18] = [
+    ("ui.background", None, Some("color0")),
+    ("ui.text", Some("color15"), None),
+    ("ui.text.focus", Some("color15"), Some("color8")),
+    ("ui.cursor", Some("color0"), Some("accent")),
+    ("ui.cursor.primary", Some("color0"), Some("accent")),
+    ("ui.cursor.match", Some("color0"), Some("color8")),
+    ("ui.selection", None, Some("color8")),
+    ("ui.linenr", Some("color8"), None),
+    ("ui.linenr.selected", Some("accent"), None),
+    ("ui.statusline", Some("color15"), Some("color8")),
+    ("ui.statusline.inactive", Some("color7"), Some("color0")),
+    ("ui.popup", None, Some("color8")),
+    ("ui.window", Some("color8"), None),
+    ("ui.help", Some("color15"), Some("color8")),
+    ("ui.menu", Some("color15"), Some("color8")),
+    ("ui.menu.selected", Some("color0"), Some("accent")),
+    ("ui.virtual.whitespace", Some("color8"), None),
+    ("ui.virtual.ruler", None, Some("color8")),
+];
+
+/// VS Code workbench colors
+const VSCODE_COLORS: [(&str, Slot); 48] = [
+    ("foreground", Slot::Index(15)),
+    ("focusBorder", Slot::Accent),
+    ("selection.background", Slot::Extra("PANEL")),
+    ("editor.background", Slot::Index(0)),
+    ("editor.foreground", Slot::Index(15)),
+    ("editor.lineHighlightBackground", Slot::Extra("PANEL")),
+    ("editor.selectionBackground", Slot::Extra("PANEL")),
+    ("editorCursor.foreground", Slot::Accent),
+    ("editorLineNumber.foreground", Slot::Index(8)),
+    ("editorLineNumber.activeForeground", Slot::Accent),
+    ("editorError.foreground", Slot::Extra("ERROR")),
+    ("editorWarning.foreground", Slot::Extra("WARNING")),
+    ("editorInfo.foreground", Slot::Extra("INFO")),
+    ("editorGroupHeader.tabsBackground", Slot::Index(0)),
+    ("tab.activeBackground", Slot::Extra("PANEL")),
+    ("tab.activeForeground", Slot::Index(15)),
+    ("tab.inactiveBackground", Slot::Index(0)),
+    ("tab.inactiveForeground", Slot::Index(7)),
+    ("sideBar.background", Slot::Index(0)),
+    ("sideBar.foreground", Slot::Index(7)),
+    ("activityBar.background", Slot::Index(0)),
+    ("activityBar.foreground", Slot::Accent),
+    ("statusBar.background", Slot::Extra("PANEL")),
+    ("statusBar.foreground", Slot::Index(15)),
+    ("titleBar.activeBackground", Slot::Index(0)),
+    ("titleBar.activeForeground", Slot::Index(15)),
+    ("panel.background", Slot::Index(0)),
+    ("terminal.background", Slot::Index(0)),
+    ("terminal.foreground", Slot::Index(15)),
+    ("terminal.ansiBlack", Slot::Index(0)),
+    ("terminal.ansiRed", Slot::Index(1)),
+    ("terminal.ansiGreen", Slot::Index(2)),
+    ("terminal.ansiYellow", Slot::Index(3)),
+    ("terminal.ansiBlue", Slot::Index(4)),
+    ("terminal.ansiMagenta", Slot::Index(5)),
+    ("terminal.ansiCyan", Slot::Index(6)),
+    ("terminal.ansiWhite", Slot::Index(7)),
+    ("terminal.ansiBrightBlack", Slot::Index(8)),
+    ("terminal.ansiBrightRed", Slot::Index(9)),
+    ("terminal.ansiBrightGreen", Slot::Index(10)),
+    ("terminal.ansiBrightYellow", Slot::Index(11)),
+    ("terminal.ansiBrightBlue", Slot::Index(12)),
+    ("terminal.ansiBrightMagenta", Slot::Index(13)),
+    ("terminal.ansiBrightCyan", Slot::Index(14)),
+    ("terminal.ansiBrightWhite", Slot::Index(15)),
+    (
+        "gitDecoration.addedResourceForeground",
+        Slot::Extra("INSERTED"),
+    ),
+    (
+        "gitDecoration.deletedResourceForeground",
+        Slot::Extra("DELETED"),
+    ),
+    (
+        "gitDecoration.modifiedResourceForeground",
+        Slot::Extra("CHANGED"),
+    ),
+];
+
+/// VS Code TextMate scopes, the extras id coloring them, and an optional font style
+const VSCODE_TOKENS: [(&[&str], &str, Option<&str>); 12] = [
+    (
+        &["comment", "punctuation.definition.comment"],
+        "COMMENT",
+        Some("italic"),
+    ),
+    (&["keyword", "storage", "storage.type"], "KEYWORD", None),
+    (&["string", "string.quoted"], "STRING", None),
+    (
+        &["constant", "constant.numeric", "constant.language"],
+        "CONSTANT",
+        None,
+    ),
+    (
+        &["entity.name.function", "support.function"],
+        "FUNCTION",
+        None,
+    ),
+    (
+        &[
+            "entity.name.type",
+            "entity.name.class",
+            "support.type",
+            "support.class",
+        ],
+        "TYPE",
+        None,
+    ),
+    (&["variable", "variable.parameter"], "VARIABLE", None),
+    (&["entity.name.tag"], "TAG", None),
+    (&["invalid"], "ERROR", None),
+    (&["markup.inserted"], "INSERTED", None),
+    (&["markup.deleted"], "DELETED", None),
+    (&["markup.changed"], "CHANGED", None),
+];
+
+/// tmTheme global settings
+const TMTHEME_GLOBAL: [(&str, Slot); 6] = [
+    ("background", Slot::Index(0)),
+    ("foreground", Slot::Index(15)),
+    ("caret", Slot::Accent),
+    ("lineHighlight", Slot::Index(8)),
+    ("selection", Slot::Index(8)),
+    ("gutterForeground", Slot::Index(7)),
+];
+
+/// tmTheme scope groups as (name, scopes, extras id, font style)
+const TMTHEME_SCOPES: [(&str, &str, &str, Option<&str>); 11] = [
+    ("Comment", "comment", "COMMENT", Some("italic")),
+    ("Keyword", "keyword, storage", "KEYWORD", None),
+    ("String", "string", "STRING", None),
+    (
+        "Number",
+        "constant.numeric, constant.language, constant.character",
+        "CONSTANT",
+        None,
+    ),
+    (
+        "Function",
+        "entity.name.function, support.function",
+        "FUNCTION",
+        None,
+    ),
+    (
+        "Type",
+        "entity.name.type, entity.name.class, support.type, support.class",
+        "TYPE",
+        None,
+    ),
+    ("Variable", "variable", "VARIABLE", None),
+    ("Invalid", "invalid", "ERROR", None),
+    ("Diff Inserted", "markup.inserted", "INSERTED", None),
+    ("Diff Deleted", "markup.deleted", "DELETED", None),
+    ("Diff Changed", "markup.changed", "CHANGED", None),
+];
+
+const PLIST_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+"#;
+
+/// Text safe inside XML content and attribute values.
+/// Control characters XML 1.0 can't hold are dropped
+fn escape_xml(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            '\t' | '\n' | '\r' => result.push(c),
+            c if c.is_control() => (),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// The subset of XML property lists tmTheme uses
+enum Plist {
+    String(String),
+    Array(Vec<Plist>),
+    Dict(Vec<(&'static str, Plist)>),
+}
+
+impl Plist {
+    fn string(text: &str) -> Self {
+        Self::String(text.to_string())
+    }
+
+    /// Appends the value as XML indented `depth` levels, escaping every string and key
+    fn write(&self, out: &mut String, depth: usize) {
+        let indent = "    ".repeat(depth);
+        match self {
+            Self::String(text) => {
+                *out += &format!("{}<string>{}</string>\n", indent, escape_xml(text))
+            }
+            Self::Array(items) => {
+                *out += &format!("{}<array>\n", indent);
+                for item in items {
+                    item.write(out, depth + 1)
+                }
+                *out += &format!("{}</array>\n", indent);
+            }
+            Self::Dict(pairs) => {
+                *out += &format!("{}<dict>\n", indent);
+                for (key, value) in pairs {
+                    *out += &format!("{}    <key>{}</key>\n", indent, escape_xml(key));
+                    value.write(out, depth + 1)
+                }
+                *out += &format!("{}</dict>\n", indent);
+            }
+        }
+    }
+}
+
+/// Emacs faces as (face, foreground, background, further attributes)
+const EMACS_FACES: [(&str, Option<Slot>, Option<Slot>, &str); 24] = [
+    ("default", Some(Slot::Index(15)), Some(Slot::Index(0)), ""),
+    ("cursor", None, Some(Slot::Accent), ""),
+    ("fringe", None, Some(Slot::Index(0)), ""),
+    ("region", None, Some(Slot::Extra("REGION")), ""),
+    ("highlight", Some(Slot::Index(0)), Some(Slot::Accent), ""),
+    ("minibuffer-prompt", Some(Slot::Accent), None, ""),
+    ("link", Some(Slot::Index(12)), None, ":underline t"),
+    ("line-number", Some(Slot::Index(8)), None, ""),
+    ("line-number-current-line", Some(Slot::Accent), None, ""),
+    (
+        "mode-line",
+        Some(Slot::Index(15)),
+        Some(Slot::Extra("MODELINE")),
+        "",
+    ),
+    (
+        "mode-line-inactive",
+        Some(Slot::Index(7)),
+        Some(Slot::Index(0)),
+        "",
+    ),
+    (
+        "font-lock-builtin-face",
+        Some(Slot::Extra("BUILTIN")),
+        None,
+        "",
+    ),
+    (
+        "font-lock-comment-face",
+        Some(Slot::Extra("COMMENT")),
+        None,
+        "",
+    ),
+    (
+        "font-lock-comment-delimiter-face",
+        Some(Slot::Extra("COMMENT")),
+        None,
+        "",
+    ),
+    (
+        "font-lock-constant-face",
+        Some(Slot::Extra("CONSTANT")),
+        None,
+        "",
+    ),
+    ("font-lock-doc-face", Some(Slot::Extra("COMMENT")), None, ""),
+    (
+        "font-lock-function-name-face",
+        Some(Slot::Extra("FUNCTION")),
+        None,
+        "",
+    ),
+    (
+        "font-lock-keyword-face",
+        Some(Slot::Extra("KEYWORD")),
+        None,
+        "",
+    ),
+    (
+        "font-lock-string-face",
+        Some(Slot::Extra("STRING")),
+        None,
+        "",
+    ),
+    ("font-lock-type-face", Some(Slot::Extra("TYPE")), None, ""),
+    (
+        "font-lock-variable-name-face",
+        Some(Slot::Extra("VARIABLE")),
+        None,
+        "",
+    ),
+    (
+        "font-lock-warning-face",
+        Some(Slot::Extra("WARNING")),
+        None,
+        "",
+    ),
+    ("error", Some(Slot::Extra("ERROR")), None, ""),
+    ("warning", Some(Slot::Extra("WARNING")), None, ""),
+];
+
+/// `text` as an Elisp string literal
+fn elisp_string(text: &str) -> String {
+    let mut result = String::from('"');
+    for c in text.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '"' => result.push_str("\\\""),
+            '\n' => result.push_str("\\n"),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// `text` with control characters as spaces, so it can't end a line comment early
+fn single_line(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+/// ANSI names of 0..=7 and 8..=15
+const ANSI: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl Builder {
+    /// Builds the whole file from the exporter's resolved colors.
+    /// `extras` are indices into `hex`. A missing id fails as the `{IDHEX}` token
+    /// a template would leave, and one past 15 as `ExtraOutOfRange`
+    pub fn build(
+        self,
+        exporter: &str,
+        data: &Collurgy,
+        hex: &[String; 16],
+        extras: &HashMap<String, usize>,
+    ) -> Result<String, CollurgyError> {
+        let index = |id: &str| match extras.get(id) {
+            Some(n) if *n < hex.len() => Ok(*n),
+            Some(n) => Err(CollurgyError::ExtraOutOfRange {
+                exporter: exporter.to_string(),
+                id: id.to_string(),
+                index: *n,
+            }),
+            None => Err(CollurgyError::InvalidToken(vec![format!("{{{}HEX}}", id)])),
+        };
+        let extra = |id: &str| index(id).map(|n| hex[n].as_str());
+        let slot = |slot: Slot| match slot {
+            Slot::Index(n) => Ok(hex[n].as_str()),
+            Slot::Extra(id) => extra(id),
+            Slot::Accent => Ok(hex[data.accent.min(15)].as_str()),
+        };
+        match self {
+            Self::Alacritty => {
+                let mut colors = toml::Table::new();
+                colors.insert(
+                    "primary".to_string(),
+                    strings([
+                        ("background", hex[0].as_str()),
+                        ("foreground", hex[15].as_str()),
+                        ("dim_foreground", hex[7].as_str()),
+                    ]),
+                );
+                colors.insert(
+                    "cursor".to_string(),
+                    strings([
+                        ("cursor", extra("CURSOR")?),
+                        ("text", extra("CURSOR_TEXT")?),
+                    ]),
+                );
+                colors.insert(
+                    "selection".to_string(),
+                    strings([
+                        ("background", extra("SELECTION")?),
+                        ("text", extra("SELECTION_TEXT")?),
+                    ]),
+                );
+                colors.insert(
+                    "normal".to_string(),
+                    strings(ANSI.into_iter().zip(hex[..8].iter().map(String::as_str))),
+                );
+                colors.insert(
+                    "bright".to_string(),
+                    strings(ANSI.into_iter().zip(hex[8..].iter().map(String::as_str))),
+                );
+
+                let mut window = toml::Table::new();
+                // through the decimal string so 0.9 doesn't widen into 0.8999999761581421
+                window.insert(
+                    "opacity".to_string(),
+                    toml::Value::Float(data.alpha.to_string().parse().unwrap_or(1.0)),
+                );
+
+                let mut root = toml::Table::new();
+                root.insert("colors".to_string(), toml::Value::Table(colors));
+                root.insert("window".to_string(), toml::Value::Table(window));
+                Ok(toml::to_string(&root).unwrap_or_default())
+            }
+            Self::Helix => {
+                let mut root = toml::Table::new();
+                for (scope, id) in HELIX_SCOPES {
+                    root.insert(
+                        scope.to_string(),
+                        toml::Value::String(format!("color{}", index(id)?)),
+                    );
+                }
+                for (scope, fg, bg) in HELIX_UI {
+                    let value = match (fg, bg) {
+                        (Some(fg), None) => toml::Value::String(fg.to_string()),
+                        _ => strings(
+                            [("fg", fg), ("bg", bg)]
+                                .into_iter()
+                                .filter_map(|(k, v)| Some((k, v?))),
+                        ),
+                    };
+                    root.insert(scope.to_string(), value);
+                }
+                for (scope, id) in [
+                    ("diagnostic.error", "ERROR"),
+                    ("diagnostic.warning", "WARNING"),
+                ] {
+                    let color = format!("color{}", index(id)?);
+                    let mut style = toml::Table::new();
+                    style.insert(
+                        "underline".to_string(),
+                        strings([("color", color.as_str()), ("style", "curl")]),
+                    );
+                    root.insert(scope.to_string(), toml::Value::Table(style));
+                }
+
+                let mut palette: toml::Table = hex
+                    .iter()
+                    .enumerate()
+                    .map(|(n, hex)| (format!("color{}", n), toml::Value::String(hex.clone())))
+                    .collect();
+                palette.insert(
+                    "accent".to_string(),
+                    toml::Value::String(hex[data.accent.min(15)].clone()),
+                );
+                root.insert("palette".to_string(), toml::Value::Table(palette));
+
+                // comments aren't part of the TOML data model, so the name goes on top
+                Ok(format!(
+                    "# {}\n{}",
+                    single_line(&data.name),
+                    toml::to_string(&root).unwrap_or_default()
+                ))
+            }
+            Self::VSCode => {
+                let colors = VSCODE_COLORS
+                    .into_iter()
+                    .map(|(key, s)| Ok((key.to_string(), slot(s)?.into())))
+                    .collect::<Result<serde_json::Map<String, serde_json::Value>, CollurgyError>>(
+                    )?;
+                let tokens = VSCODE_TOKENS
+                    .into_iter()
+                    .map(|(scope, id, style)| {
+                        let mut settings = serde_json::json!({ "foreground": extra(id)? });
+                        if let Some(style) = style {
+                            settings["fontStyle"] = style.into();
+                        }
+                        Ok(serde_json::json!({ "scope": scope, "settings": settings }))
+                    })
+                    .collect::<Result<Vec<serde_json::Value>, CollurgyError>>()?;
+                let kind = if data.is_dark() { "dark" } else { "light" };
+                let theme = serde_json::json!({
+                    "name": data.name,
+                    "type": kind,
+                    "colors": colors,
+                    "tokenColors": tokens,
+                });
+                Ok(serde_json::to_string_pretty(&theme).unwrap_or_default())
+            }
+            Self::TmTheme => {
+                let global = TMTHEME_GLOBAL
+                    .into_iter()
+                    .map(|(key, s)| Ok((key, Plist::string(slot(s)?))))
+                    .collect::<Result<Vec<(&str, Plist)>, CollurgyError>>()?;
+                let mut settings = vec![Plist::Dict(vec![("settings", Plist::Dict(global))])];
+                for (name, scope, id, style) in TMTHEME_SCOPES {
+                    let mut colors = vec![("foreground", Plist::string(extra(id)?))];
+                    if let Some(style) = style {
+                        colors.push(("fontStyle", Plist::string(style)))
+                    }
+                    settings.push(Plist::Dict(vec![
+                        ("name", Plist::string(name)),
+                        ("scope", Plist::string(scope)),
+                        ("settings", Plist::Dict(colors)),
+                    ]));
+                }
+                let root = Plist::Dict(vec![
+                    ("name", Plist::string(&data.name)),
+                    ("settings", Plist::Array(settings)),
+                ]);
+                let mut result = PLIST_HEADER.to_string();
+                root.write(&mut result, 0);
+                result += "</plist>\n";
+                Ok(result)
+            }
+            Self::Emacs => {
+                let mut faces = Vec::new();
+                for (face, fg, bg, rest) in EMACS_FACES {
+                    let mut attributes = Vec::new();
+                    if let Some(fg) = fg {
+                        attributes.push(format!(":foreground {}", elisp_string(slot(fg)?)))
+                    }
+                    if let Some(bg) = bg {
+                        attributes.push(format!(":background {}", elisp_string(slot(bg)?)))
+                    }
+                    if !rest.is_empty() {
+                        attributes.push(rest.to_string())
+                    }
+                    faces.push(format!(" '({} ((t ({}))))", face, attributes.join(" ")));
+                }
+                let ansi: Vec<String> = hex[..8].iter().map(|h| elisp_string(h)).collect();
+                Ok(format!(
+                    ";;; collurgy-theme.el --- {} -*- lexical-binding: t -*-\n\
+                     ;; Generated by Collurgy\n\
+                     \n\
+                     (deftheme collurgy {})\n\
+                     \n\
+                     (custom-theme-set-faces\n 'collurgy\n{})\n\
+                     \n\
+                     (custom-theme-set-variables\n 'collurgy\n \
+                     '(ansi-color-names-vector\n   [{}]))\n\
+                     \n\
+                     (provide-theme 'collurgy)\n\
+                     ;;; collurgy-theme.el ends here\n",
+                    single_line(&data.name),
+                    elisp_string(&data.name),
+                    faces.join("\n"),
+                    ansi.join(" "),
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use colcon::{convert_space_chunked, Space};
+
+use super::Collurgy;
+
+/// Each spectrum color against the background, and each normal against its bright
+pub const DEFAULT_PAIRS: [(usize, usize); 18] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (0, 5),
+    (0, 6),
+    (0, 9),
+    (0, 10),
+    (0, 11),
+    (0, 12),
+    (0, 13),
+    (0, 14),
+    (1, 9),
+    (2, 10),
+    (3, 11),
+    (4, 12),
+    (5, 13),
+    (6, 14),
+];
+
+/// WCAG 2 relative luminance of an SRGB color
+pub fn relative_luminance(srgb: [f32; 3]) -> f32 {
+    let [r, g, b] = srgb.map(|c| {
+        let c = c.clamp(0.0, 1.0);
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// WCAG 2 contrast ratio, 1.0 ..= 21.0
+pub fn contrast(a: [f32; 3], b: [f32; 3]) -> f32 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Luminance where a color contrasts equally with black and white,
+/// `sqrt(1.05 * 0.05) - 0.05`. Backgrounds below it are dark
+pub const DARK_THRESHOLD: f32 = 0.179;
+
+/// Oklab distance under which two palette colors are hard to tell apart
+pub const DUPLICATE_THRESHOLD: f32 = 0.03;
+
+/// Euclidean distance between two Oklab colors
+pub fn oklab_distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - y).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
+
+impl Collurgy {
+    /// Whether the computed background luminance is under [`DARK_THRESHOLD`]
+    pub fn is_dark(&self) -> bool {
+        relative_luminance(self.compute()[0]) < DARK_THRESHOLD
+    }
+
+    /// lowest contrast among `pairs` of computed indices
+    pub fn min_contrast(&self, pairs: &[(usize, usize)]) -> f32 {
+        let colors = self.compute();
+        pairs
+            .iter()
+            .filter(|(a, b)| *a < 16 && *b < 16)
+            .map(|(a, b)| contrast(colors[*a], colors[*b]))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    /// Computed index pairs closer than `threshold` in Oklab, alongside their distance
+    pub fn similar_pairs(&self, threshold: f32) -> Vec<(usize, usize, f32)> {
+        let mut colors = self.compute();
+        convert_space_chunked(Space::SRGB, Space::OKLAB, &mut colors);
+        let mut result = Vec::new();
+        for a in 0..16 {
+            for b in a + 1..16 {
+                let distance = oklab_distance(&colors[a], &colors[b]);
+                if distance < threshold {
+                    result.push((a, b, distance))
+                }
+            }
+        }
+        result
+    }
+
+    /// Adjusts the spectrum and spectrum_bright lightness to maximize the minimum
+    /// contrast among `pairs`, stopping early once `target` is reached.
+    /// Returns the achieved minimum.
+    pub fn solve_contrast(&mut self, pairs: &[(usize, usize)], target: f32) -> f32 {
+        let mut best = self.min_contrast(pairs);
+        let mut step = 10.0;
+        // coordinate descent that halves the step whenever neither direction helps
+        for _ in 0..200 {
+            if best >= target || step < 0.5 {
+                break;
+            }
+            let mut improved = false;
+            for bright in [false, true] {
+                for dir in [step, -step] {
+                    let original = self.lightness(bright);
+                    *self.lightness_mut(bright) = (original + dir).clamp(0.0, 100.0);
+                    let score = self.min_contrast(pairs);
+                    if score > best {
+                        best = score;
+                        improved = true;
+                    } else {
+                        *self.lightness_mut(bright) = original;
+                    }
+                }
+            }
+            if !improved {
+                step /= 2.0;
+            }
+        }
+        best
+    }
+
+    fn lightness(&self, bright: bool) -> f32 {
+        if bright {
+            self.spectrum_bright[0]
+        } else {
+            self.spectrum[0]
+        }
+    }
+
+    fn lightness_mut(&mut self, bright: bool) -> &mut f32 {
+        if bright {
+            &mut self.spectrum_bright[0]
+        } else {
+            &mut self.spectrum[0]
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::{fmt::Display, io, path::PathBuf};
+
+/// Everything loading, parsing, or exporting a theme can fail with
+#[derive(Debug)]
+pub enum CollurgyError {
+    /// Reading or writing `PathBuf` failed
+    Io(PathBuf, io::Error),
+    /// Text was neither a valid TOML nor JSON theme
+    Parse(String),
+    /// No exporter or output by this name
+    UnknownExporter(String),
+    /// `{TOKEN}`s left over after exporting
+    InvalidToken(Vec<String>),
+    /// Exporter whose formatter is empty or only whitespace
+    EmptyFormatter(String),
+    /// An extras entry pointing past the 16 colors
+    ExtraOutOfRange {
+        exporter: String,
+        id: String,
+        index: usize,
+    },
+}
+
+impl Display for CollurgyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "I/O error on {}: {}", path.display(), e),
+            Self::Parse(e) => write!(f, "Failed to parse {}", e),
+            Self::UnknownExporter(name) => write!(f, "Unknown output {}", name),
+            Self::InvalidToken(tokens) => write!(f, "Unresolved tokens {}", tokens.join(" ")),
+            Self::EmptyFormatter(name) => write!(f, "{} has a blank formatter", name),
+            Self::ExtraOutOfRange {
+                exporter,
+                id,
+                index,
+            } => write!(
+                f,
+                "{} extra {} is color {}, which is past 15",
+                exporter, id, index
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CollurgyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
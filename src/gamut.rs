@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+/// Display gamut the theme targets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Gamut {
+    #[default]
+    SRGB,
+    /// Display-P3, same D65 white point and transfer curve as SRGB
+    P3,
+}
+
+/// Linear SRGB to linear Display-P3
+const SRGB_TO_P3: [[f32; 3]; 3] = [
+    [0.822_462_1, 0.177_538, 0.0],
+    [0.033_194_1, 0.966_805_8, 0.0],
+    [0.017_082_7, 0.072_397_4, 0.910_519_9],
+];
+
+/// Linear Display-P3 to linear SRGB
+const P3_TO_SRGB: [[f32; 3]; 3] = [
+    [1.224_940_1, -0.224_940_4, 0.0],
+    [-0.042_056_9, 1.042_057_1, 0.0],
+    [-0.019_637_6, -0.078_636_1, 1.098_273_5],
+];
+
+fn eotf(c: f32) -> f32 {
+    if c.abs() <= 0.04045 {
+        c / 12.92
+    } else {
+        c.signum() * ((c.abs() + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn oetf(c: f32) -> f32 {
+    if c.abs() <= 0.0031308 {
+        c * 12.92
+    } else {
+        c.signum() * (1.055 * c.abs().powf(1.0 / 2.4) - 0.055)
+    }
+}
+
+fn mul(matrix: [[f32; 3]; 3], pixel: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row.iter().zip(pixel.iter()).map(|(m, c)| m * c).sum())
+}
+
+/// Linear SRGB into linear Display-P3. Out of range values pass through
+fn lrgb_to_lp3(lrgb: [f32; 3]) -> [f32; 3] {
+    mul(SRGB_TO_P3, lrgb)
+}
+
+impl Gamut {
+    pub fn next(self) -> Self {
+        match self {
+            Self::SRGB => Self::P3,
+            Self::P3 => Self::SRGB,
+        }
+    }
+
+    /// SRGB clamped per channel into this gamut.
+    /// Display-P3 colors keep SRGB values outside 0..=1 where P3 reaches past SRGB
+    pub fn clip(self, srgb: [f32; 3]) -> [f32; 3] {
+        match self {
+            Self::SRGB => srgb.map(|c| c.clamp(0.0, 1.0)),
+            Self::P3 => mul(
+                P3_TO_SRGB,
+                lrgb_to_lp3(srgb.map(eotf)).map(|c| c.clamp(0.0, 1.0)),
+            )
+            .map(oetf),
+        }
+    }
+
+    /// SRGB into Display-P3, first clipping to this gamut
+    /// so the values describe the same color the SRGB tokens do
+    pub fn srgb_to_p3(self, srgb: [f32; 3]) -> [f32; 3] {
+        lrgb_to_lp3(self.clip(srgb).map(eotf)).map(|c| oetf(c).clamp(0.0, 1.0))
+    }
+
+    /// Whether a linear SRGB pixel lies inside this gamut
+    pub fn contains_linear(self, lrgb: &[f32; 3], tolerance: f32) -> bool {
+        let pixel = match self {
+            Self::SRGB => *lrgb,
+            Self::P3 => lrgb_to_lp3(*lrgb),
+        };
+        pixel
+            .iter()
+            .all(|c| (-tolerance..=1.0 + tolerance).contains(c))
+    }
+
+    /// Whether an SRGB pixel lies inside this gamut
+    pub fn contains(self, srgb: &[f32; 3], tolerance: f32) -> bool {
+        match self {
+            Self::SRGB => srgb
+                .iter()
+                .all(|c| (-tolerance..=1.0 + tolerance).contains(c)),
+            Self::P3 => self.contains_linear(&srgb.map(eotf), tolerance),
+        }
+    }
+}
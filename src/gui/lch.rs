@@ -1,12 +1,51 @@
-use crate::apply_space;
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+use crate::{apply_space, gamut::Gamut};
 use colcon::Space;
 use eframe::{
-    egui::{self, Label, RichText, Sense, TextureOptions, Widget},
+    egui::{self, Label, RichText, Sense, TextureHandle, TextureOptions, Widget},
     epaint::{Color32, ColorImage, Rect, Rgba, Stroke},
 };
 
+/// Recently used picker textures, shared by every picker so identical ones are only built once
+#[derive(Default)]
+pub struct TextureCache {
+    entries: VecDeque<(u64, TextureHandle)>,
+}
+
+impl TextureCache {
+    /// enough for the four pickers' CH squares and L strips plus a little churn
+    const CAPACITY: usize = 16;
+
+    /// Returns the texture for `key`, building it with `image` on a miss
+    fn get(
+        &mut self,
+        ctx: &egui::Context,
+        key: impl Hash,
+        options: TextureOptions,
+        image: impl FnOnce() -> ColorImage,
+    ) -> TextureHandle {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let key = hasher.finish();
+        if let Some(n) = self.entries.iter().position(|(k, _)| *k == key) {
+            let entry = self.entries.remove(n).unwrap();
+            self.entries.push_front(entry);
+        } else {
+            let texture = ctx.load_texture(format!("picker {:x}", key), image(), options);
+            self.entries.push_front((key, texture));
+            self.entries.truncate(Self::CAPACITY);
+        }
+        self.entries[0].1.clone()
+    }
+}
+
 pub struct LCH<'a> {
     value: &'a mut [f32; 3],
+    cache: &'a mut TextureCache,
     text: String,
     fill: Color32,
     font_size: f32,
@@ -14,32 +53,168 @@ pub struct LCH<'a> {
     space: Space,
     high2023: f32,
     spectrum: bool,
-    clip: bool,
+    gamut: Gamut,
+    clip: Clip,
+    smooth: bool,
+    hue_snap: f32,
+    chroma_lock: bool,
+    /// L, C, H drag rounding
+    steps: [f32; 3],
 }
 
-fn clip(pixels: &mut [[f32; 3]]) {
-    let fill: [f32; 3] = colcon::str2space("oklab 50% 0 0", Space::SRGB).unwrap();
-    pixels.iter_mut().for_each(|p| {
-        if p.iter().any(|c| *c < 0.0 || *c > 1.0) {
-            *p = fill
+/// Settings every picker in a frame shares
+#[derive(Clone, Copy)]
+pub struct PickerOptions {
+    pub font_size: f32,
+    pub scale: f32,
+    pub space: Space,
+    pub high2023: f32,
+    pub gamut: Gamut,
+    pub clip: Clip,
+    pub smooth: bool,
+    pub hue_snap: f32,
+    pub chroma_lock: bool,
+    /// L, C, H drag rounding
+    pub steps: [f32; 3],
+}
+
+/// How picker pixels outside of the gamut are drawn
+#[derive(Clone, Copy, PartialEq, Hash)]
+pub enum Clip {
+    /// Pass values through as-is
+    None,
+    /// Replace the whole pixel with neutral gray
+    Gray,
+    /// Clamp each channel, keeping a rough idea of the hue
+    Clamp,
+}
+
+impl Clip {
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::Gray,
+            Self::Gray => Self::Clamp,
+            Self::Clamp => Self::None,
         }
+    }
+}
+
+/// Takes linear SRGB
+fn out_of_gamut(pixel: &[f32; 3], gamut: Gamut) -> bool {
+    !gamut.contains_linear(pixel, 0.0)
+}
+
+fn clip(pixels: &mut [[f32; 3]], mode: Clip, gamut: Gamut) {
+    match mode {
+        Clip::None => (),
+        Clip::Gray => {
+            let fill: [f32; 3] = colcon::str2space("oklab 50% 0 0", Space::SRGB).unwrap();
+            pixels.iter_mut().for_each(|p| {
+                if out_of_gamut(p, gamut) {
+                    *p = fill
+                }
+            })
+        }
+        Clip::Clamp => pixels
+            .iter_mut()
+            .flatten()
+            .for_each(|c| *c = if c.is_nan() { 0.0 } else { c.clamp(0.0, 1.0) }),
+    }
+}
+
+/// Rounds to the nearest multiple of `step`, passing through when it's 0
+fn snap(value: f32, step: f32) -> f32 {
+    if step > 0.0 {
+        (value / step).round() * step
+    } else {
+        value
+    }
+}
+
+/// Right minus left and up minus down presses while `response` has focus,
+/// keeping the arrows from moving focus elsewhere
+fn arrow_keys(ui: &egui::Ui, response: &egui::Response) -> (f32, f32) {
+    if !response.has_focus() {
+        return (0.0, 0.0);
+    }
+    ui.memory_mut(|m| {
+        m.set_focus_lock_filter(
+            response.id,
+            egui::EventFilter {
+                horizontal_arrows: true,
+                vertical_arrows: true,
+                ..Default::default()
+            },
+        )
+    });
+    ui.input(|i| {
+        let key = |k| i.key_pressed(k) as i32 as f32;
+        (
+            key(egui::Key::ArrowRight) - key(egui::Key::ArrowLeft),
+            key(egui::Key::ArrowUp) - key(egui::Key::ArrowDown),
+        )
     })
 }
 
+/// Top of the picker's chroma axis. Chroma 100 is the model's 95th percentile
+/// SRGB chroma, so this stretches to the 100th to keep the whole gamut reachable
+pub fn chroma_axis(space: Space) -> f32 {
+    if space == Space::HSV {
+        100.0
+    } else {
+        100.0 * space.srgb_quants()[100][1] / space.srgb_quants()[95][1]
+    }
+}
+
+/// Highest chroma at lightness `l` and hue `h` that stays inside `gamut`
+pub fn max_chroma(space: Space, l: f32, h: f32, high2023: f32, gamut: Gamut) -> f32 {
+    let in_gamut = |c: f32| {
+        let mut pixel = [[l, c, h]];
+        apply_space(space, &mut pixel, Space::LRGB, high2023);
+        !out_of_gamut(&pixel[0], gamut)
+    };
+    if !in_gamut(0.0) {
+        return 0.0;
+    }
+    let (mut lo, mut hi) = (0.0, chroma_axis(space));
+    if in_gamut(hi) {
+        return hi;
+    }
+    for _ in 0..16 {
+        let mid = (lo + hi) / 2.0;
+        if in_gamut(mid) {
+            lo = mid
+        } else {
+            hi = mid
+        }
+    }
+    lo
+}
+
 impl<'a> LCH<'a> {
     pub fn new(
         value: &'a mut [f32; 3],
+        cache: &'a mut TextureCache,
         text: impl ToString,
         fill: Color32,
-        font_size: f32,
-        scale: f32,
-        space: Space,
-        high2023: f32,
         spectrum: bool,
-        clip: bool,
+        options: PickerOptions,
     ) -> Self {
+        let PickerOptions {
+            font_size,
+            scale,
+            space,
+            high2023,
+            gamut,
+            clip,
+            smooth,
+            hue_snap,
+            chroma_lock,
+            steps,
+        } = options;
         Self {
             value,
+            cache,
             text: text.to_string(),
             fill,
             font_size,
@@ -47,23 +222,47 @@ impl<'a> LCH<'a> {
             space,
             high2023,
             spectrum,
+            gamut,
             clip,
+            smooth,
+            hue_snap,
+            chroma_lock,
+            steps,
         }
     }
 }
 
 impl<'a> Widget for LCH<'a> {
     fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        // texel centers stay put either way, so the crosshair math holds
+        let filter = if self.smooth {
+            TextureOptions::LINEAR
+        } else {
+            TextureOptions::NEAREST
+        };
+        let axis = chroma_axis(self.space);
         ui.vertical(|ui| {
             let mut fg = [*self.value];
             apply_space(self.space, &mut fg, Space::LRGB, self.high2023);
             let fg = fg[0];
+            let clipped = out_of_gamut(&fg, self.gamut);
+            // a decimal once the steps go below whole units
+            let digits = self.steps.map(|step| (step > 0.0 && step < 1.0) as usize);
             let fg: Color32 = Rgba::from_rgb(fg[0], fg[1], fg[2]).into();
+            // crosshairs flag values compute() will clip
+            let cross = if clipped { Color32::RED } else { self.fill };
             ui.add(
                 Label::new(
                     RichText::new(format!(
-                        "{} {:.0} {:.0} {:.0}",
-                        self.text, self.value[0], self.value[1], self.value[2]
+                        "{} {:.*} {:.*} {:.*}{}",
+                        self.text,
+                        digits[0],
+                        self.value[0],
+                        digits[1],
+                        self.value[1],
+                        digits[2],
+                        self.value[2],
+                        if clipped { " (clipped)" } else { "" }
                     ))
                     .size(self.font_size)
                     .background_color(self.fill)
@@ -85,9 +284,16 @@ impl<'a> Widget for LCH<'a> {
                         if let Some(pos) = chresponse.interact_pointer_pos() {
                             if chrect.contains(pos) {
                                 let (x, y) = (pos - chrect.left_top()).into();
-                                self.value[1] = 100.0 - (y / self.scale - 0.5).round().max(0.0);
-                                self.value[2] =
-                                    (x / self.scale - 0.5).round().clamp(0.0, 71.0).abs() * 5.0;
+                                let c = (100.0 - (y / self.scale - 0.5)) * axis / 100.0;
+                                self.value[1] = snap(c.clamp(0.0, axis), self.steps[1]).max(0.0);
+                                let h = (x / self.scale - 0.5).clamp(0.0, 71.0) * 5.0;
+                                let mut hue = snap(h, self.steps[2]).rem_euclid(360.0);
+                                // Ctrl snaps to even angles
+                                if self.hue_snap > 0.0 && ui.input(|i| i.modifiers.command) {
+                                    hue = ((hue / self.hue_snap).round() * self.hue_snap)
+                                        .rem_euclid(360.0)
+                                }
+                                self.value[2] = hue;
                             }
                         }
                     }
@@ -95,41 +301,94 @@ impl<'a> Widget for LCH<'a> {
                         if let Some(pos) = lresponse.interact_pointer_pos() {
                             if lrect.contains(pos) {
                                 let y = (pos - lrect.left_top()).y;
-                                self.value[0] = 100.0 - (y / self.scale - 0.5).round().max(0.0);
+                                let l = 100.0 - (y / self.scale - 0.5);
+                                self.value[0] =
+                                    snap(l.clamp(0.0, 100.0), self.steps[0]).clamp(0.0, 100.0);
+                                // track the gamut edge instead of leaving it
+                                if self.chroma_lock {
+                                    self.value[1] = self.value[1].min(max_chroma(
+                                        self.space,
+                                        self.value[0],
+                                        self.value[2],
+                                        self.high2023,
+                                        self.gamut,
+                                    ))
+                                }
                             }
                         }
                     }
+                    // arrows step hue and chroma on the square, lightness on the strip
+                    let step = |n: usize, fallback: f32| {
+                        if self.steps[n] > 0.0 {
+                            self.steps[n]
+                        } else {
+                            fallback
+                        }
+                    };
+                    let (lstep, cstep, hstep) = (step(0, 1.0), step(1, 1.0), step(2, 1.0));
+                    let (x, y) = arrow_keys(ui, &chresponse);
+                    if x != 0.0 || y != 0.0 {
+                        self.value[2] = (self.value[2] + x * hstep).rem_euclid(360.0);
+                        self.value[1] = (self.value[1] + y * cstep).clamp(0.0, axis);
+                    }
+                    let (_, y) = arrow_keys(ui, &lresponse);
+                    if y != 0.0 {
+                        self.value[0] = (self.value[0] + y * lstep).clamp(0.0, 100.0);
+                        if self.chroma_lock {
+                            self.value[1] = self.value[1].min(max_chroma(
+                                self.space,
+                                self.value[0],
+                                self.value[2],
+                                self.high2023,
+                                self.gamut,
+                            ))
+                        }
+                    }
                     // CH Square
                     let chpaint = ui.painter_at(chrect);
 
-                    let mut pixels: Vec<[f32; 3]> = (0..=100)
-                        .map(|c| {
-                            (0..72)
-                                .map(|h| [self.value[0], (100 - c) as f32, h as f32 * 5.0])
-                                .collect::<Vec<[f32; 3]>>()
-                        })
-                        .reduce(|mut acc, e| {
-                            acc.extend_from_slice(&e);
-                            acc
-                        })
-                        .unwrap();
-
-                    apply_space(self.space, &mut pixels, Space::LRGB, self.high2023);
-                    if self.clip {
-                        clip(&mut pixels)
-                    }
+                    let (space, high2023, clip_mode, gamut) =
+                        (self.space, self.high2023, self.clip, self.gamut);
+                    let (l, c, h) = (self.value[0], self.value[1], self.value[2]);
+                    let chtexture = self.cache.get(
+                        ui.ctx(),
+                        (
+                            "CH",
+                            format!("{:?}", space),
+                            high2023.to_bits(),
+                            l.to_bits(),
+                            axis.to_bits(),
+                            clip_mode,
+                            gamut,
+                            self.smooth,
+                        ),
+                        filter,
+                        || {
+                            let mut pixels: Vec<[f32; 3]> = (0..=100)
+                                .map(|c| {
+                                    (0..72)
+                                        .map(|h| {
+                                            [l, (100 - c) as f32 * axis / 100.0, h as f32 * 5.0]
+                                        })
+                                        .collect::<Vec<[f32; 3]>>()
+                                })
+                                .reduce(|mut acc, e| {
+                                    acc.extend_from_slice(&e);
+                                    acc
+                                })
+                                .unwrap();
 
-                    let chimg = ColorImage {
-                        size: [72, 101],
-                        pixels: pixels
-                            .into_iter()
-                            .map(|p| Rgba::from_rgb(p[0], p[1], p[2]).into())
-                            .collect::<Vec<Color32>>(),
-                    };
-                    let chtexture = ui.ctx().load_texture(
-                        format!("{} CH", self.text),
-                        chimg,
-                        TextureOptions::NEAREST,
+                            apply_space(space, &mut pixels, Space::LRGB, high2023);
+                            clip(&mut pixels, clip_mode, gamut);
+
+                            ColorImage {
+                                size: [72, 101],
+                                pixels: pixels
+                                    .into_iter()
+                                    .map(|p| Rgba::from_rgb(p[0], p[1], p[2]).into())
+                                    .collect::<Vec<Color32>>(),
+                            }
+                        },
                     );
                     chpaint.image(
                         chtexture.id(),
@@ -140,7 +399,7 @@ impl<'a> Widget for LCH<'a> {
                     let chpos = chrect.left_top()
                         + (
                             (self.value[2] / 5.0 + 0.5) * self.scale,
-                            (100.0 - self.value[1] + 0.5) * self.scale,
+                            (100.0 - self.value[1] * 100.0 / axis + 0.5) * self.scale,
                         )
                             .into();
 
@@ -152,7 +411,7 @@ impl<'a> Widget for LCH<'a> {
                                 chpos + (x * 5.0 * self.scale, y * 5.0 * self.scale).into(),
                             ],
                             Stroke {
-                                color: self.fill,
+                                color: cross,
                                 width: 0.5 * self.scale,
                             },
                         );
@@ -184,26 +443,35 @@ impl<'a> Widget for LCH<'a> {
                     // L slider
                     let lpaint = ui.painter_at(lrect);
 
-                    let mut pixels: Vec<[f32; 3]> = (0..=100)
-                        .map(|l| [(100 - l) as f32, self.value[1], self.value[2]])
-                        .collect::<Vec<[f32; 3]>>();
+                    let ltexture = self.cache.get(
+                        ui.ctx(),
+                        (
+                            "L",
+                            format!("{:?}", space),
+                            high2023.to_bits(),
+                            c.to_bits(),
+                            h.to_bits(),
+                            clip_mode,
+                            gamut,
+                            self.smooth,
+                        ),
+                        filter,
+                        || {
+                            let mut pixels: Vec<[f32; 3]> = (0..=100)
+                                .map(|l| [(100 - l) as f32, c, h])
+                                .collect::<Vec<[f32; 3]>>();
 
-                    apply_space(self.space, &mut pixels, Space::LRGB, self.high2023);
-                    if self.clip {
-                        clip(&mut pixels)
-                    }
+                            apply_space(space, &mut pixels, Space::LRGB, high2023);
+                            clip(&mut pixels, clip_mode, gamut);
 
-                    let limg = ColorImage {
-                        size: [1, 101],
-                        pixels: pixels
-                            .into_iter()
-                            .map(|p| Rgba::from_rgb(p[0], p[1], p[2]).into())
-                            .collect::<Vec<Color32>>(),
-                    };
-                    let ltexture = ui.ctx().load_texture(
-                        format!("{} L", self.text),
-                        limg,
-                        TextureOptions::NEAREST,
+                            ColorImage {
+                                size: [1, 101],
+                                pixels: pixels
+                                    .into_iter()
+                                    .map(|p| Rgba::from_rgb(p[0], p[1], p[2]).into())
+                                    .collect::<Vec<Color32>>(),
+                            }
+                        },
                     );
                     lpaint.image(
                         ltexture.id(),
@@ -221,15 +489,43 @@ impl<'a> Widget for LCH<'a> {
                                 lpos + (x * 7.0 * self.scale, y * 3.0 * self.scale).into(),
                             ],
                             Stroke {
-                                color: self.fill,
+                                color: cross,
                                 width: 0.5 * self.scale,
                             },
                         );
                     }
+                    for (rect, response) in [(chrect, &chresponse), (lrect, &lresponse)] {
+                        if response.has_focus() {
+                            ui.painter_at(rect).rect_stroke(
+                                rect.shrink(0.5 * self.scale),
+                                0.0,
+                                Stroke::new(self.scale, self.fill),
+                            );
+                        }
+                    }
                     chresponse.union(lresponse)
                 })
                 .response;
 
+            ui.add(
+                Label::new(
+                    RichText::new(format!(
+                        "Max chroma {:.0}",
+                        max_chroma(
+                            self.space,
+                            self.value[0],
+                            self.value[2],
+                            self.high2023,
+                            self.gamut,
+                        )
+                    ))
+                    .size(self.font_size * 0.8)
+                    .background_color(self.fill)
+                    .color(fg),
+                )
+                .wrap(false),
+            );
+
             // let mut hex = [*self.value];
             // apply_space(self.space, &mut hex, Space::SRGB, self.high2023);
             // let hex = colcon::irgb_to_hex(colcon::srgb_to_irgb(hex[0]));
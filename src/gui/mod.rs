@@ -1,9 +1,11 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     env,
     fmt::Display,
     fs::{self, read_to_string},
     ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
 };
 
 use colcon::{srgb_to_irgb, Space};
@@ -11,7 +13,7 @@ use colcon::{srgb_to_irgb, Space};
 use eframe::{
     egui::{
         self, CentralPanel, Context, DragValue, Frame, Grid, Label, Rgba, RichText, ScrollArea,
-        Sense, SidePanel, Widget,
+        Sense, SidePanel, TopBottomPanel, Widget,
     },
     emath::Align2,
     epaint::{Color32, Rounding, Stroke},
@@ -19,21 +21,42 @@ use eframe::{
 };
 
 mod lch;
-use lch::LCH;
+use lch::{Clip, PickerOptions, TextureCache, LCH};
 use rfd::FileDialog;
+use serde::{Deserialize, Serialize};
 
-use super::{Collurgy, Exporter};
+use super::{
+    apply_space, contrast, expand_home,
+    gamut::Gamut,
+    names, parse_hex, parse_theme,
+    presets::{load_user_presets, presets, save_user_presets},
+    unapply_space, unresolved_tokens,
+    validate::{Issue, Severity},
+    Collurgy, CollurgyError, Exporter, Output,
+};
 
 const LI: &'static str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat. Duis aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur. Excepteur sint occaecat cupidatat non proident, sunt in culpa qui officia deserunt mollit anim id est laborum.";
 
+/// eframe storage key of the user's extras edits
+const EXTRAS_KEY: &str = "user_extras";
+
 fn scale_factor() -> f32 {
-    if let Ok(val) = env::var("GDK_DPI_SCALE") {
-        val.parse::<f32>().expect("Bad GDK_DPI_SCALE value")
-    } else if let Ok(val) = env::var("GDK_SCALE") {
-        val.parse::<f32>().expect("Bad GDK_SCALE value")
-    } else {
-        1.0
+    scale_from(|key| env::var(key).ok())
+}
+
+/// First of COLLURGY_SCALE, GDK_DPI_SCALE or GDK_SCALE that `lookup` finds, or 1.0.
+/// Unparseable or non-positive values are skipped with a warning.
+/// WINIT_X11_SCALE_FACTOR isn't read since winit already applies it
+fn scale_from(lookup: impl Fn(&str) -> Option<String>) -> f32 {
+    for key in ["COLLURGY_SCALE", "GDK_DPI_SCALE", "GDK_SCALE"] {
+        if let Some(val) = lookup(key) {
+            match val.trim().parse::<f32>() {
+                Ok(scale) if scale.is_finite() && scale > 0.0 => return scale,
+                _ => eprintln!("Ignoring bad {} value {:?}", key, val),
+            }
+        }
     }
+    1.0
 }
 
 // ColorButton {{{
@@ -58,10 +81,12 @@ impl ColorButton {
 impl Widget for ColorButton {
     fn ui(mut self, ui: &mut egui::Ui) -> egui::Response {
         let response = ui.allocate_response(ui.available_size(), Sense::click());
-        if response.is_pointer_button_down_on() {
+        // only while the press is still over the button, so dragging off reverts it
+        if response.is_pointer_button_down_on() && response.contains_pointer() {
             (self.color, self.fill) = (self.fill, self.color);
         }
-        if response.hovered() {
+        // framed while hovered or keyboard focused
+        if response.hovered() || response.has_focus() {
             ui.painter()
                 .rect(response.rect, Rounding::ZERO, self.color, Stroke::NONE);
             ui.painter().rect(
@@ -143,6 +168,28 @@ impl<'a> ColorScale<'a> {
 impl<'a> Widget for ColorScale<'a> {
     fn ui(self, ui: &mut egui::Ui) -> egui::Response {
         let response = ui.allocate_response(ui.available_size(), Sense::click_and_drag());
+        if response.has_focus() {
+            // keep Left/Right for stepping instead of moving focus
+            ui.memory_mut(|m| {
+                m.set_focus_lock_filter(
+                    response.id,
+                    egui::EventFilter {
+                        horizontal_arrows: true,
+                        ..Default::default()
+                    },
+                )
+            });
+            let (left, right) = ui.input(|i| {
+                (
+                    i.key_pressed(egui::Key::ArrowLeft),
+                    i.key_pressed(egui::Key::ArrowRight),
+                )
+            });
+            let delta = (right as i32 - left as i32) as f32 * self.round;
+            if delta != 0.0 {
+                *self.value = (*self.value + delta).clamp(*self.range.start(), *self.range.end())
+            }
+        }
         if response.dragged() {
             if let Some(pos) = response.hover_pos() {
                 *self.value = ((pos.x - response.rect.left())
@@ -153,6 +200,13 @@ impl<'a> Widget for ColorScale<'a> {
                     .clamp(*self.range.start(), *self.range.end())
             }
         }
+        // live value by the cursor, with as many decimals as the rounding has
+        if response.dragged() {
+            let digits = (-self.round.log10()).ceil().max(0.0) as usize;
+            egui::show_tooltip_at_pointer(ui.ctx(), response.id.with("readout"), |ui| {
+                ui.label(format!("{:.*}", digits, *self.value))
+            });
+        }
 
         let (r1, r2) = response.rect.split_left_right_at_fraction(
             (*self.value - self.range.start()) / (self.range.end() - self.range.start()),
@@ -184,76 +238,626 @@ impl<'a> Widget for ColorScale<'a> {
             self.color,
         );
 
+        if response.has_focus() {
+            ui.set_clip_rect(response.rect);
+            ui.painter().rect_stroke(
+                response.rect.shrink(1.0),
+                Rounding::ZERO,
+                Stroke::new(2.0, self.color),
+            );
+        }
+
         response
     }
 }
 // ColorScale }}}
 
-pub enum Output {
-    Exporter(String),
-    JSON,
-    TOML,
+/// The 16 colors as one row of small squares
+fn swatch_strip(ui: &mut egui::Ui, colors: [[f32; 3]; 16]) {
+    let (rect, _) = ui.allocate_exact_size((16.0 * 12.0, 12.0).into(), Sense::hover());
+    for (n, color) in colors.into_iter().enumerate() {
+        let [r, g, b] = srgb_to_irgb(color);
+        let min = rect.left_top() + egui::vec2(n as f32 * 12.0, 0.0);
+        ui.painter().rect_filled(
+            egui::Rect::from_min_size(min, (12.0, 12.0).into()),
+            Rounding::ZERO,
+            Color32::from_rgb(r, g, b),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum SwatchLayout {
+    #[default]
+    Wide,
+    Square,
+    Tall,
+}
+
+impl SwatchLayout {
+    const ALL: [Self; 3] = [Self::Wide, Self::Square, Self::Tall];
+    fn columns(&self) -> usize {
+        match self {
+            Self::Wide => 8,
+            Self::Square => 4,
+            Self::Tall => 2,
+        }
+    }
 }
 
-impl Display for Output {
+impl Display for SwatchLayout {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{}x{}", self.columns(), 16 / self.columns()))
+    }
+}
+
+/// UI preferences persisted through eframe storage
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    layout: SwatchLayout,
+    /// LINEAR picker textures instead of discrete cells
+    smooth: bool,
+    /// indented JSON for Save/JSON and Save/Palette
+    pretty: bool,
+    /// model switches convert the colors instead of reinterpreting them
+    convert_model: bool,
+    /// degrees the picker hue snaps to while Ctrl is held
+    hue_snap: f32,
+    /// L slider drags pull chroma down to stay in gamut
+    chroma_lock: bool,
+    /// draw the app controls in fixed neutrals instead of the edited theme
+    fixed_chrome: bool,
+    /// fg, bg indices of the lorem ipsum blocks
+    preview_pairs: [(usize, usize); 4],
+    /// L, C, H rounding while dragging a picker. 0 leaves values unrounded
+    steps: [f32; 3],
+    /// Oklab distance under which two colors are flagged as duplicates
+    duplicate_threshold: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            layout: SwatchLayout::default(),
+            smooth: false,
+            pretty: true,
+            convert_model: true,
+            hue_snap: 15.0,
+            chroma_lock: false,
+            fixed_chrome: false,
+            preview_pairs: [(15, 0), (7, 0), (15, 8), (7, 8)],
+            steps: [1.0, 1.0, 5.0],
+            duplicate_threshold: contrast::DUPLICATE_THRESHOLD,
+        }
+    }
+}
+
+/// An open theme and the file it was last loaded from or saved to
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Tab {
+    data: Collurgy,
+    path: Option<PathBuf>,
+    /// content hash when last loaded or saved, to spot unsaved edits
+    saved: u64,
+}
+
+impl Tab {
+    fn new(data: Collurgy, path: Option<PathBuf>) -> Self {
+        Self {
+            saved: data.content_hash(),
+            data,
+            path,
+        }
+    }
+}
+
+/// Everything kept under eframe's APP_KEY
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Stored {
+    settings: Settings,
+    tabs: Vec<Tab>,
+    tab: usize,
+}
+
+/// A save held back until replacing existing files is confirmed
+#[derive(Clone)]
+enum Overwrite {
+    /// `write_output` to the path
+    Output(PathBuf),
+    /// `write_pair` beside the path
+    Pair(PathBuf),
+}
+
+impl Overwrite {
+    /// the files that would be replaced
+    fn existing(&self) -> Vec<PathBuf> {
         match self {
-            Output::Exporter(s) => f.write_fmt(format_args!("Export/{}", s)),
-            Output::JSON => f.write_str("Save/JSON"),
-            Output::TOML => f.write_str("Save/TOML"),
+            Self::Output(path) => vec![path.clone()],
+            Self::Pair(path) => pair_paths(path).into(),
+        }
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect()
+    }
+}
+
+/// `path` with -dark and -light added to the file stem
+fn pair_paths(path: &Path) -> [PathBuf; 2] {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    ["dark", "light"].map(|variant| path.with_file_name(format!("{}-{}{}", stem, variant, ext)))
+}
+
+/// Per-theme results too slow to redo every frame
+struct Analysis {
+    /// content hash and duplicate threshold these were computed for
+    key: (u64, u32),
+    duplicates: Vec<(usize, usize, f32)>,
+    issues: Vec<Issue>,
+    /// the rendered colors read back into the theme's model
+    lch: [[f32; 3]; 16],
+    /// min, mean and max contrast of the default pairs
+    contrast: [f32; 3],
+}
+
+impl Analysis {
+    fn new(data: &Collurgy, exporters: &HashMap<String, Exporter>, threshold: f32) -> Self {
+        let srgb = data.compute();
+        let mut lch = srgb;
+        unapply_space(data.model, &mut lch, Space::SRGB);
+        let ratios: Vec<f32> = contrast::DEFAULT_PAIRS
+            .iter()
+            .map(|(a, b)| contrast::contrast(srgb[*a], srgb[*b]))
+            .collect();
+        Self {
+            key: (data.content_hash(), threshold.to_bits()),
+            duplicates: data.similar_pairs(threshold),
+            issues: data.validate(exporters),
+            lch,
+            contrast: [
+                ratios.iter().copied().fold(f32::INFINITY, f32::min),
+                ratios.iter().sum::<f32>() / ratios.len() as f32,
+                ratios.iter().copied().fold(0.0, f32::max),
+            ],
         }
     }
 }
 
+/// A command palette entry
+type Action = Box<dyn FnOnce(&mut CollurgyUI, &Context)>;
+
 pub struct CollurgyUI {
+    /// the active tab
     data: Collurgy,
+    /// all open tabs. The active slot's theme is a placeholder while it lives in `data`
+    tabs: Vec<Tab>,
+    tab: usize,
+    /// Close was pressed on a tab with unsaved edits
+    close_prompt: bool,
     exporters: HashMap<String, Exporter>,
     output: Output,
     scale: f32,
-    clip: bool,
+    clip: Clip,
+    user_presets: BTreeMap<String, Collurgy>,
+    preset_name: String,
+    contrast_target: f32,
+    contrast_achieved: Option<f32>,
+    settings: Settings,
+    /// save awaiting overwrite confirmation
+    overwrite: Option<Overwrite>,
+    toasts: Vec<(String, Instant)>,
+    /// spectrum and spectrum_bright hue edits move together
+    link_hue: bool,
+    /// comparison theme previewed at `blend_t` between the active theme and it
+    blend: Option<Collurgy>,
+    blend_t: f32,
+    /// hex buffer for the swatch pin menu
+    pin_hex: String,
+    /// exporters rewritten to their `path` whenever the theme changes
+    auto_apply: HashSet<String>,
+    /// content hash of the last auto-applied theme
+    auto_hash: u64,
+    /// when an unwritten change was first seen, for debouncing
+    auto_pending: Option<Instant>,
+    auto_written: Option<Instant>,
+    /// command palette filter text, open while Some
+    palette: Option<String>,
+    /// theme file from the command line, watched for outside edits
+    source: Option<PathBuf>,
+    source_modified: Option<SystemTime>,
+    /// content hash right after the source was last loaded, to spot unsaved edits
+    source_hash: u64,
+    source_checked: Instant,
+    /// reload without asking when there are no unsaved edits
+    auto_reload: bool,
+    textures: TextureCache,
+    /// the source changed but there are unsaved edits
+    reload_prompt: bool,
+    /// extras edited in the GUI, persisted and laid over every loaded theme.
+    /// Precedence is these, then the theme's own extras, then exporter defaults
+    user_extras: HashMap<String, HashMap<String, usize>>,
+    analysis: Analysis,
+    /// kept alive since some platforms drop clipboard contents with their owner.
+    /// None where the clipboard can't be opened
+    clipboard: Option<arboard::Clipboard>,
 }
 
 impl CollurgyUI {
     // {{{
     pub fn new(
-        _cc: &CreationContext,
+        cc: &CreationContext,
         mut data: Collurgy,
+        source: Option<PathBuf>,
         exporters: HashMap<String, Exporter>,
     ) -> Self {
-        // Fill missing extras into data
-        for (k, v) in exporters.iter() {
-            if let Some(extras) = &v.extras {
-                if !data.extras.contains_key(k) {
-                    data.extras.insert(k.to_string(), extras.clone());
-                }
-            }
+        let user_extras: HashMap<String, HashMap<String, usize>> = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, EXTRAS_KEY))
+            .unwrap_or_default();
+        data.fill_extras(&exporters);
+        data.overlay_extras(&user_extras);
+        let stored: Stored = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+        let (mut tabs, mut tab) = (stored.tabs, stored.tab);
+        // a theme from the command line opens beside the restored tabs
+        if source.is_some() || tabs.is_empty() {
+            tabs.push(Tab::new(data, source.clone()));
+            tab = tabs.len() - 1;
         }
+        let tab = tab.min(tabs.len() - 1);
+        let data = std::mem::take(&mut tabs[tab].data);
+        let settings = stored.settings;
         Self {
+            analysis: Analysis::new(&data, &exporters, settings.duplicate_threshold),
+            user_extras,
+            source_modified: source
+                .as_ref()
+                .and_then(|p| fs::metadata(p).and_then(|m| m.modified()).ok()),
+            source_hash: data.content_hash(),
+            source,
+            source_checked: Instant::now(),
+            auto_reload: false,
+            textures: TextureCache::default(),
+            reload_prompt: false,
+            clipboard: arboard::Clipboard::new().ok(),
             data,
+            tabs,
+            tab,
+            close_prompt: false,
             output: Output::TOML,
             exporters,
             scale: scale_factor(),
-            clip: false,
+            clip: Clip::None,
+            user_presets: load_user_presets(),
+            preset_name: String::new(),
+            contrast_target: 3.0,
+            contrast_achieved: None,
+            settings,
+            overwrite: None,
+            toasts: Vec::new(),
+            link_hue: false,
+            blend: None,
+            blend_t: 0.5,
+            pin_hex: String::new(),
+            auto_apply: HashSet::new(),
+            auto_hash: 0,
+            auto_pending: None,
+            auto_written: None,
+            palette: None,
+        }
+    }
+    fn process_output(&self) -> Result<String, CollurgyError> {
+        self.output
+            .render(&self.data, &self.exporters, self.settings.pretty)
+    }
+    /// recomputes the analysis once the theme or duplicate threshold changed
+    fn refresh_analysis(&mut self) {
+        let key = (
+            self.data.content_hash(),
+            self.settings.duplicate_threshold.to_bits(),
+        );
+        if key != self.analysis.key {
+            self.analysis = Analysis::new(
+                &self.data,
+                &self.exporters,
+                self.settings.duplicate_threshold,
+            )
         }
     }
-    fn process_output(&self) -> String {
-        match &self.output {
-            Output::Exporter(s) => self.exporters[s].export(&self.data),
-            Output::JSON => serde_json::to_string(&self.data).unwrap(),
-            Output::TOML => toml::to_string(&self.data).unwrap(),
+    /// queue a transient message in the corner of the window
+    fn notify(&mut self, message: impl ToString) {
+        self.toasts.push((message.to_string(), Instant::now()))
+    }
+    /// puts the swatch grid on the clipboard as an image
+    fn copy_image(&mut self) {
+        let Some(clipboard) = self.clipboard.as_mut() else {
+            return;
+        };
+        let (bytes, width, height) = self.data.render_swatches(32, 8);
+        let image = arboard::ImageData {
+            width: width as usize,
+            height: height as usize,
+            bytes: bytes.into(),
+        };
+        match clipboard.set_image(image) {
+            Ok(()) => self.notify("Copied palette image"),
+            Err(e) => self.notify(format!("Could not copy image: {}", e)),
         }
     }
-    fn apply_serial(&mut self, data: &str) {
-        if let Ok(collurgy) = toml::from_str(data) {
-            self.data = collurgy
-        } else if let Ok(collurgy) = serde_json::from_str(data) {
-            self.data = collurgy
+    fn write_output(&mut self, path: &Path) {
+        match self.process_output() {
+            Ok(text) => {
+                // a saved theme becomes the tab's file
+                if self.write_text(path, text) && matches!(self.output, Output::TOML | Output::JSON)
+                {
+                    self.tabs[self.tab].path = Some(path.to_path_buf());
+                    self.tabs[self.tab].saved = self.data.content_hash();
+                }
+            }
+            Err(e) => self.notify(e),
         }
     }
+    /// returns whether the file was written
+    fn write_text(&mut self, path: &Path, text: String) -> bool {
+        if let Some(dir) = path.parent() {
+            if let Err(e) = fs::create_dir_all(dir) {
+                self.notify(CollurgyError::Io(dir.to_path_buf(), e));
+                return false;
+            }
+        }
+        match fs::write(path, text) {
+            Ok(()) => {
+                self.notify(format!("Saved {}", path.display()));
+                true
+            }
+            Err(e) => {
+                self.notify(CollurgyError::Io(path.to_path_buf(), e));
+                false
+            }
+        }
+    }
+    /// writes the theme and its inversion next to `path` with -dark/-light suffixes
+    fn write_pair(&mut self, path: &Path) {
+        let inverted = self.data.inverted();
+        let (dark, light) = if self.data.is_dark() {
+            (&self.data, &inverted)
+        } else {
+            (&inverted, &self.data)
+        };
+        let (dark, light) = match (
+            self.output
+                .render(dark, &self.exporters, self.settings.pretty),
+            self.output
+                .render(light, &self.exporters, self.settings.pretty),
+        ) {
+            (Ok(dark), Ok(light)) => (dark, light),
+            (Err(e), _) | (_, Err(e)) => return self.notify(e),
+        };
+        let [dark_path, light_path] = pair_paths(path);
+        self.write_text(&dark_path, dark);
+        self.write_text(&light_path, light);
+    }
+    fn write(&mut self, save: Overwrite) {
+        match save {
+            Overwrite::Output(path) => self.write_output(&path),
+            Overwrite::Pair(path) => self.write_pair(&path),
+        }
+    }
+    /// replaces the active theme with a serialized one
+    fn apply_serial(&mut self, data: &str) -> Result<(), CollurgyError> {
+        let mut collurgy = parse_theme(data)?;
+        self.adopt_extras(&mut collurgy);
+        self.data = collurgy;
+        self.notify(format!("Loaded {}", self.data.name));
+        Ok(())
+    }
+    /// loads a theme file into the active tab, which then tracks it for unsaved edits
+    fn load_file(&mut self, path: &Path) {
+        let result = read_to_string(path)
+            .map_err(|e| CollurgyError::Io(path.to_path_buf(), e))
+            .and_then(|s| self.apply_serial(&s));
+        match result {
+            Ok(()) => {
+                self.tabs[self.tab].path = Some(path.to_path_buf());
+                self.tabs[self.tab].saved = self.data.content_hash();
+            }
+            Err(e) => self.notify(e),
+        }
+    }
+    fn switch_tab(&mut self, tab: usize) {
+        if tab != self.tab && tab < self.tabs.len() {
+            std::mem::swap(&mut self.data, &mut self.tabs[self.tab].data);
+            std::mem::swap(&mut self.data, &mut self.tabs[tab].data);
+            self.tab = tab;
+        }
+    }
+    /// whether the active tab changed since it was last loaded or saved
+    fn tab_edited(&self) -> bool {
+        self.data.content_hash() != self.tabs[self.tab].saved
+    }
+    /// exporter defaults for missing extras, then the user's edits on top
+    fn adopt_extras(&self, data: &mut Collurgy) {
+        data.fill_extras(&self.exporters);
+        data.overlay_extras(&self.user_extras);
+    }
+    fn open_tab(&mut self, mut data: Collurgy) {
+        self.adopt_extras(&mut data);
+        self.tabs.push(Tab::new(data, None));
+        self.switch_tab(self.tabs.len() - 1)
+    }
+    fn close_tab(&mut self) {
+        if self.tabs.len() > 1 {
+            self.tabs.remove(self.tab);
+            self.tab = self.tab.min(self.tabs.len() - 1);
+            self.data = std::mem::take(&mut self.tabs[self.tab].data);
+        }
+        self.close_prompt = false;
+    }
+    /// reloads the source file into the active tab
+    fn reload_source(&mut self) {
+        if let Some(path) = self.source.clone() {
+            match read_to_string(&path) {
+                Ok(s) => match self.apply_serial(&s) {
+                    Ok(()) => {
+                        self.source_hash = self.data.content_hash();
+                        self.tabs[self.tab].saved = self.source_hash;
+                    }
+                    Err(e) => self.notify(e),
+                },
+                Err(e) => self.notify(CollurgyError::Io(path, e)),
+            }
+        }
+        self.reload_prompt = false;
+    }
+    /// polls the source file's mtime, reloading or prompting once it settles
+    fn watch_source(&mut self, ctx: &Context) {
+        const POLL: Duration = Duration::from_millis(500);
+        let Some(path) = &self.source else {
+            return;
+        };
+        ctx.request_repaint_after(POLL);
+        if self.source_checked.elapsed() < POLL {
+            return;
+        }
+        self.source_checked = Instant::now();
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == self.source_modified {
+            return;
+        }
+        self.source_modified = modified;
+        if self.auto_reload && self.data.content_hash() == self.source_hash {
+            self.reload_source()
+        } else {
+            self.reload_prompt = true
+        }
+    }
+    /// writes every auto-apply exporter once the theme has been still for a moment
+    fn auto_apply(&mut self, ctx: &Context) {
+        const DEBOUNCE: Duration = Duration::from_millis(100);
+        if self.auto_apply.is_empty() {
+            self.auto_pending = None;
+            return;
+        }
+        let hash = self.data.content_hash();
+        if hash != self.auto_hash {
+            self.auto_hash = hash;
+            self.auto_pending = Some(Instant::now());
+        }
+        let Some(pending) = self.auto_pending else {
+            return;
+        };
+        if pending.elapsed() < DEBOUNCE {
+            ctx.request_repaint_after(DEBOUNCE - pending.elapsed());
+            return;
+        }
+        self.auto_pending = None;
+        let mut names: Vec<String> = self.auto_apply.iter().cloned().collect();
+        names.sort();
+        for name in names {
+            let Some(path) = self.exporters.get(&name).and_then(|e| e.path.as_deref()) else {
+                continue;
+            };
+            let path = expand_home(path);
+            let output =
+                Output::Exporter(name).render(&self.data, &self.exporters, self.settings.pretty);
+            let result = output.and_then(|output| {
+                path.parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(&path, output))
+                    .map_err(|e| CollurgyError::Io(path.clone(), e))
+            });
+            if let Err(e) = result {
+                self.notify(format!("Auto-apply failed: {}", e));
+            }
+        }
+        self.auto_written = Some(Instant::now());
+    }
+    /// everything the command palette can run
+    fn commands(&self) -> Vec<(String, Action)> {
+        fn command(
+            name: impl ToString,
+            action: impl FnOnce(&mut CollurgyUI, &Context) + 'static,
+        ) -> (String, Action) {
+            (name.to_string(), Box::new(action))
+        }
+        let mut commands = vec![
+            command("Copy output", |ui, ctx| match ui.process_output() {
+                Ok(text) => {
+                    ctx.output_mut(|o| o.copied_text = text);
+                    ui.notify(format!("Copied {}", ui.output));
+                }
+                Err(e) => ui.notify(e),
+            }),
+            command("Invert lightness", |ui, _| ui.data = ui.data.inverted()),
+            command("Cycle clip mode", |ui, _| ui.clip = ui.clip.next()),
+            command("Cycle gamut", |ui, _| ui.data.gamut = ui.data.gamut.next()),
+            command("Toggle link hue", |ui, _| ui.link_hue = !ui.link_hue),
+            command("Toggle smooth pickers", |ui, _| {
+                ui.settings.smooth = !ui.settings.smooth
+            }),
+            command("Toggle fixed chrome", |ui, _| {
+                ui.settings.fixed_chrome = !ui.settings.fixed_chrome
+            }),
+            command("New tab", |ui, _| ui.open_tab(Collurgy::default())),
+            command("Duplicate tab", |ui, _| ui.open_tab(ui.data.clone())),
+            command("Solve contrast", |ui, _| {
+                ui.contrast_achieved = Some(
+                    ui.data
+                        .solve_contrast(&contrast::DEFAULT_PAIRS, ui.contrast_target),
+                )
+            }),
+        ];
+        let mut names: Vec<&String> = self.exporters.keys().collect();
+        names.sort();
+        let outputs = names
+            .into_iter()
+            .map(|name| Output::Exporter(name.clone()))
+            .chain([Output::JSON, Output::TOML, Output::Palette]);
+        for output in outputs {
+            commands.push(command(output.to_string(), move |ui, _| ui.output = output));
+        }
+        for preset in presets()
+            .into_iter()
+            .chain(self.user_presets.values().cloned())
+        {
+            commands.push(command(format!("Preset/{}", preset.name), move |ui, _| {
+                ui.apply_preset(preset)
+            }));
+        }
+        commands
+    }
+    /// replaces the theme but keeps the current exporter extras
+    fn apply_preset(&mut self, preset: Collurgy) {
+        let extras = std::mem::take(&mut self.data.extras);
+        self.data = preset;
+        self.data.extras = extras;
+    }
     // }}}
 }
 
 impl App for CollurgyUI {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        let mut tabs = self.tabs.clone();
+        tabs[self.tab].data = self.data.clone();
+        let stored = Stored {
+            settings: self.settings.clone(),
+            tabs,
+            tab: self.tab,
+        };
+        eframe::set_value(storage, eframe::APP_KEY, &stored);
+        eframe::set_value(storage, EXTRAS_KEY, &self.user_extras);
+    }
+
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
         // {{{
         // DnD
@@ -261,20 +865,66 @@ impl App for CollurgyUI {
             for f in &input.raw.dropped_files {
                 if let Some(bytes) = &f.bytes {
                     if let Ok(s) = std::str::from_utf8(bytes) {
-                        self.apply_serial(s)
+                        if let Err(e) = self.apply_serial(s) {
+                            self.notify(e)
+                        }
                     }
                 } else if let Some(path) = &f.path {
-                    if let Ok(s) = read_to_string(path) {
-                        self.apply_serial(&s)
-                    }
+                    self.load_file(path)
                 }
             }
         });
         let s = self.scale;
-        let colors: [Color32; 16] = self.data.compute().map(|c| {
+        self.refresh_analysis();
+        let blended = self
+            .blend
+            .as_ref()
+            .map(|other| self.data.lerp(other, self.blend_t));
+        let (srgb, in_gamut) = blended.as_ref().unwrap_or(&self.data).compute_checked();
+        let colors: [Color32; 16] = srgb.map(|c| {
             let c = srgb_to_irgb(c);
             Color32::from_rgb(c[0], c[1], c[2])
         });
+        // app controls, kept readable regardless of the theme when fixed_chrome is set
+        let (chrome_fg, chrome_bg) = if self.settings.fixed_chrome {
+            (Color32::from_gray(220), Color32::from_gray(40))
+        } else {
+            (colors[self.data.accent_role(1)], colors[0])
+        };
+        // TABS {{{
+        TopBottomPanel::top("Tabs").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let mut switch = None;
+                for n in 0..self.tabs.len() {
+                    let name = if n == self.tab {
+                        &self.data.name
+                    } else {
+                        &self.tabs[n].data.name
+                    };
+                    if ui.selectable_label(n == self.tab, name).clicked() {
+                        switch = Some(n)
+                    }
+                }
+                if let Some(n) = switch {
+                    self.switch_tab(n)
+                }
+                ui.separator();
+                if ui.button("New").clicked() {
+                    self.open_tab(Collurgy::default())
+                }
+                if ui.button("Duplicate").clicked() {
+                    self.open_tab(self.data.clone())
+                }
+                if self.tabs.len() > 1 && ui.button("Close").clicked() {
+                    if self.tab_edited() {
+                        self.close_prompt = true
+                    } else {
+                        self.close_tab()
+                    }
+                }
+            });
+        });
+        // TABS }}}
         SidePanel::right("ExportPan")
             .min_width(200.0)
             .show(ctx, |ui| {
@@ -297,11 +947,28 @@ impl App for CollurgyUI {
                             self.output = Output::TOML;
                             ui.close_menu();
                         }
+                        if ui.button("Save/Palette").clicked() {
+                            self.output = Output::Palette;
+                            ui.close_menu();
+                        }
                     });
+                    if matches!(self.output, Output::JSON | Output::Palette) {
+                        ui.checkbox(&mut self.settings.pretty, "Pretty");
+                    }
                     if ui.button("Copy").clicked() {
-                        ui.output_mut(|o| {
-                            o.copied_text = self.process_output();
-                        });
+                        match self.process_output() {
+                            Ok(text) => {
+                                ui.output_mut(|o| o.copied_text = text);
+                                self.notify(format!("Copied {}", self.output));
+                            }
+                            Err(e) => self.notify(e),
+                        }
+                    }
+                    if ui
+                        .add_enabled(self.clipboard.is_some(), egui::Button::new("Copy image"))
+                        .clicked()
+                    {
+                        self.copy_image()
                     }
                     if ui.button("Save").clicked() {
                         let mut dialog = FileDialog::new();
@@ -321,20 +988,49 @@ impl App for CollurgyUI {
                             }
                             Output::TOML => dialog = dialog.set_file_name("collurgy.toml"),
                             Output::JSON => dialog = dialog.set_file_name("collurgy.json"),
+                            Output::Palette => {
+                                dialog = dialog.set_file_name("collurgy-palette.json")
+                            }
                         }
                         // on Wayland this has like a 75% chance of making egui go poof
                         if let Some(file) = dialog.save_file() {
-                            let _ = fs::write(file, self.process_output());
+                            let save = Overwrite::Output(file);
+                            if save.existing().is_empty() {
+                                self.write(save)
+                            } else {
+                                self.overwrite = Some(save)
+                            }
+                        }
+                    }
+                    if let Output::Exporter(s) = &self.output {
+                        if ui
+                            .button("Save pair")
+                            .on_hover_text("Writes -dark and -light files, one lightness-inverted")
+                            .clicked()
+                        {
+                            let mut dialog = FileDialog::new();
+                            if let Some(p) = &self.exporters[s].path {
+                                if let Some(name) = p.file_name() {
+                                    dialog = dialog.set_file_name(name.to_string_lossy())
+                                }
+                            }
+                            if let Some(file) = dialog.save_file() {
+                                let save = Overwrite::Pair(file);
+                                if save.existing().is_empty() {
+                                    self.write(save)
+                                } else {
+                                    self.overwrite = Some(save)
+                                }
+                            }
                         }
                     }
                     if ui.button("Load").clicked() {
                         let dialog = FileDialog::new()
                             .set_file_name("collurgy.toml")
-                            .add_filter("Serialized Collurgy", &["toml", "json"]);
+                            .add_filter("Serialized Collurgy", &["toml", "json"])
+                            .add_filter("base16 scheme", &["yaml", "yml"]);
                         if let Some(path) = dialog.pick_file() {
-                            if let Ok(s) = read_to_string(path) {
-                                self.apply_serial(&s)
-                            }
+                            self.load_file(&path)
                         }
                     }
                 });
@@ -342,13 +1038,46 @@ impl App for CollurgyUI {
                 // EXPORTER {{{
                 ScrollArea::both().show(ui, |ui| {
                     if let Output::Exporter(e) = &self.output {
-                        if let Some(extras) = self.data.extras.get_mut(&self.exporters[e].name) {
+                        if let (true, Some(path)) =
+                            (self.exporters[e].shadows_builtin, &self.exporters[e].source)
+                        {
+                            ui.label(format!("{} overrides the builtin", path.display()));
+                        }
+                        if let Some(path) = &self.exporters[e].path {
+                            ui.horizontal(|ui| {
+                                let mut on = self.auto_apply.contains(e);
+                                if ui.checkbox(&mut on, "Auto-apply").changed() {
+                                    if on {
+                                        self.auto_apply.insert(e.clone());
+                                        // force a write so the file matches right away
+                                        self.auto_hash = 0;
+                                    } else {
+                                        self.auto_apply.remove(e);
+                                    }
+                                }
+                                if on {
+                                    ui.label(match self.auto_written {
+                                        Some(time) => format!(
+                                            "{} written {:.0}s ago",
+                                            path.display(),
+                                            time.elapsed().as_secs_f32()
+                                        ),
+                                        None => format!("{} pending", path.display()),
+                                    });
+                                }
+                            });
+                        }
+                        let name = self.exporters[e].name.clone();
+                        if let Some(extras) = self.data.extras.get_mut(&name) {
+                            let defaults = self.exporters[e].extras.as_ref();
+                            // ids the user set, or reset to the default with None
+                            let mut changes: Vec<(String, Option<usize>)> = Vec::new();
                             let mut sorted: Vec<(&String, &mut usize)> =
                                 extras.iter_mut().collect();
                             sorted.sort_unstable_by(|a, b| a.0.cmp(b.0));
                             for (id, n) in sorted.into_iter() {
-                                if *n < 16 {
-                                    ui.horizontal(|ui| {
+                                ui.horizontal(|ui| {
+                                    if *n < 16 {
                                         ui.label(
                                             RichText::new(id)
                                                 .background_color(if *n != 0 {
@@ -358,21 +1087,73 @@ impl App for CollurgyUI {
                                                 })
                                                 .color(colors[*n]),
                                         );
-                                        ui.add(DragValue::new(n).clamp_range(0..=15));
-                                    });
-                                }
+                                        if ui.add(DragValue::new(n).clamp_range(0..=15)).changed() {
+                                            changes.push((id.clone(), Some(*n)))
+                                        }
+                                    } else {
+                                        // a DragValue would clamp it silently, so it's shown as is
+                                        ui.label(
+                                            RichText::new(format!("{} {} is past 15", id, n))
+                                                .color(Color32::RED),
+                                        );
+                                        if ui.small_button("Clamp").clicked() {
+                                            *n = 15;
+                                            changes.push((id.clone(), Some(15)))
+                                        }
+                                    }
+                                    if let Some(default) = defaults.and_then(|d| d.get(id)) {
+                                        if *n != *default && ui.small_button("Reset").clicked() {
+                                            *n = *default;
+                                            changes.push((id.clone(), None))
+                                        }
+                                    }
+                                });
+                            }
+                            // only the touched ids persist, so the rest keep following the theme
+                            for (id, n) in changes {
+                                let user = self.user_extras.entry(name.clone()).or_default();
+                                match n {
+                                    Some(n) => user.insert(id, n),
+                                    None => user.remove(&id),
+                                };
                             }
+                            self.user_extras.retain(|_, ids| !ids.is_empty());
                             if ui.button("Reset All").clicked() {
                                 if let Some(new_extras) = &self.exporters[e].extras {
                                     *extras = new_extras.clone()
                                 }
+                                self.user_extras.remove(&name);
                             }
                         }
                     }
+                    let output = match self.process_output() {
+                        Ok(output) => output,
+                        Err(e) => {
+                            ui.label(RichText::new(e.to_string()).color(Color32::RED));
+                            String::new()
+                        }
+                    };
+                    if let Output::Exporter(name) = &self.output {
+                        if self.exporters[name].is_blank() {
+                            ui.label(
+                                RichText::new(
+                                    CollurgyError::EmptyFormatter(name.clone()).to_string(),
+                                )
+                                .color(Color32::RED),
+                            );
+                        }
+                        let unresolved = unresolved_tokens(&output);
+                        if !unresolved.is_empty() {
+                            ui.label(
+                                RichText::new(CollurgyError::InvalidToken(unresolved).to_string())
+                                    .color(Color32::RED),
+                            );
+                        }
+                    }
                     // sneaky immutable textedit hack?
                     // ui.code_editor(&mut self.output().as_str());
                     // textedit always wraps???
-                    ui.add(Label::new(self.process_output()).wrap(false))
+                    ui.add(Label::new(output).wrap(false))
                 });
                 // EXPORTER }}}
             });
@@ -389,54 +1170,301 @@ impl App for CollurgyUI {
                             0.5..=3.0,
                             0.1,
                             format!("UI SCALE {:.1}", s),
-                            colors[self.data.accent],
-                            colors[0],
+                            chrome_fg,
+                            chrome_bg,
                             15.0,
                         ),
                     );
                     ui.add(egui::TextEdit::singleline(&mut self.data.name).desired_width(100.0));
                     ui.menu_button(format!("Model: {:?}", self.data.model), |ui| {
+                        ui.checkbox(&mut self.settings.convert_model, "Keep appearance");
                         for space in [Space::HSV].iter().chain(Space::UCS_POLAR) {
-                            if ui.button(format!("{:?}", space)).clicked() {
-                                self.data.model = *space
+                            let switched = |data: &Collurgy| {
+                                let mut data = data.clone();
+                                if self.settings.convert_model {
+                                    data.convert_model(*space)
+                                } else {
+                                    data.model = *space
+                                }
+                                data
+                            };
+                            let response = ui
+                                .button(format!("{:?}", space))
+                                .on_hover_ui(|ui| {
+                                    ui.label("Current");
+                                    swatch_strip(ui, self.data.compute());
+                                    ui.label(format!("As {:?}", space));
+                                    swatch_strip(ui, switched(&self.data).compute());
+                                });
+                            if response.clicked() {
+                                self.data = switched(&self.data)
                             }
                         }
                     });
+                    ui.menu_button("Presets", |ui| {
+                        for preset in presets() {
+                            if ui.button(&preset.name).clicked() {
+                                self.apply_preset(preset);
+                                ui.close_menu();
+                            }
+                        }
+                        ui.separator();
+                        let (mut load, mut delete) = (None, None);
+                        for (name, preset) in self.user_presets.iter() {
+                            ui.horizontal(|ui| {
+                                if ui.button(name).clicked() {
+                                    load = Some(preset.clone());
+                                    ui.close_menu();
+                                }
+                                if ui.small_button("Delete").clicked() {
+                                    delete = Some(name.clone());
+                                }
+                            });
+                        }
+                        if let Some(preset) = load {
+                            self.apply_preset(preset);
+                        }
+                        if let Some(name) = delete {
+                            self.user_presets.remove(&name);
+                            if let Err(e) = save_user_presets(&self.user_presets) {
+                                self.notify(format!("Could not save presets: {}", e))
+                            }
+                        }
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.preset_name)
+                                    .hint_text("Preset name")
+                                    .desired_width(100.0),
+                            );
+                            if ui.button("Save").clicked() && !self.preset_name.is_empty() {
+                                self.user_presets
+                                    .insert(self.preset_name.clone(), self.data.clone());
+                                if let Err(e) = save_user_presets(&self.user_presets) {
+                                    self.notify(format!("Could not save presets: {}", e))
+                                }
+                            }
+                        });
+                    });
                     if ui
                         .add_sized(
                             (160.0, 20.0),
                             color_button_toggle(
-                                "Toggle clipped values",
-                                colors[self.data.accent],
-                                colors[0],
+                                match self.clip {
+                                    Clip::None => "Clip: None",
+                                    Clip::Gray => "Clip: Gray",
+                                    Clip::Clamp => "Clip: Clamp",
+                                },
+                                chrome_fg,
+                                chrome_bg,
+                                15.0,
+                                self.clip != Clip::None,
+                            ),
+                        )
+                        .clicked()
+                    {
+                        self.clip = self.clip.next()
+                    };
+                    if ui
+                        .add_sized(
+                            (120.0, 20.0),
+                            color_button_toggle(
+                                match self.data.gamut {
+                                    Gamut::SRGB => "Gamut: sRGB",
+                                    Gamut::P3 => "Gamut: P3",
+                                },
+                                chrome_fg,
+                                chrome_bg,
+                                15.0,
+                                self.data.gamut != Gamut::SRGB,
+                            ),
+                        )
+                        .clicked()
+                    {
+                        self.data.gamut = self.data.gamut.next()
+                    };
+                    if ui
+                        .add_sized(
+                            (80.0, 20.0),
+                            color_button_toggle(
+                                "Smooth",
+                                chrome_fg,
+                                chrome_bg,
+                                15.0,
+                                self.settings.smooth,
+                            ),
+                        )
+                        .clicked()
+                    {
+                        self.settings.smooth = !self.settings.smooth
+                    };
+                    if ui
+                        .add_sized(
+                            (80.0, 20.0),
+                            color_button_toggle(
+                                "Link hue",
+                                chrome_fg,
+                                chrome_bg,
+                                15.0,
+                                self.link_hue,
+                            ),
+                        )
+                        .clicked()
+                    {
+                        self.link_hue = !self.link_hue
+                    };
+                    if ui
+                        .add_sized(
+                            (110.0, 20.0),
+                            color_button_toggle(
+                                "Reverse hue",
+                                chrome_fg,
+                                chrome_bg,
+                                15.0,
+                                self.data.reverse_hue,
+                            ),
+                        )
+                        .clicked()
+                    {
+                        self.data.reverse_hue = !self.data.reverse_hue
+                    };
+                    if ui
+                        .add_sized(
+                            (110.0, 20.0),
+                            color_button_toggle(
+                                "Equal L",
+                                chrome_fg,
+                                chrome_bg,
+                                15.0,
+                                self.data.equal_lightness,
+                            ),
+                        )
+                        .on_hover_text("Overridden primaries take the spectrum lightness")
+                        .clicked()
+                    {
+                        self.data.equal_lightness = !self.data.equal_lightness
+                    };
+                    if ui
+                        .add_sized(
+                            (110.0, 20.0),
+                            color_button_toggle(
+                                "Chroma lock",
+                                chrome_fg,
+                                chrome_bg,
                                 15.0,
-                                self.clip,
+                                self.settings.chroma_lock,
                             ),
                         )
+                        .on_hover_text("Lower chroma while dragging lightness to stay in gamut")
                         .clicked()
                     {
-                        self.clip = !self.clip
+                        self.settings.chroma_lock = !self.settings.chroma_lock
                     };
+                    ui.add(
+                        DragValue::new(&mut self.settings.hue_snap)
+                            .clamp_range(0.0..=180.0)
+                            .speed(0.5)
+                            .prefix("Ctrl snap ")
+                            .suffix("°"),
+                    )
+                    .on_hover_text("Hue step while dragging a picker with Ctrl held. 0 disables");
+                    ui.menu_button("Steps", |ui| {
+                        for (step, (name, max)) in self
+                            .settings
+                            .steps
+                            .iter_mut()
+                            .zip([("L ", 10.0), ("C ", 10.0), ("H ", 30.0)])
+                        {
+                            ui.add(
+                                DragValue::new(step)
+                                    .clamp_range(0.0..=max)
+                                    .speed(0.05)
+                                    .prefix(name),
+                            );
+                        }
+                    })
+                    .response
+                    .on_hover_text("Picker rounding while dragging. 0 disables");
+                    if ui
+                        .add_sized(
+                            (110.0, 20.0),
+                            color_button_toggle(
+                                "Fixed chrome",
+                                chrome_fg,
+                                chrome_bg,
+                                15.0,
+                                self.settings.fixed_chrome,
+                            ),
+                        )
+                        .on_hover_text("Draw controls in neutral colors, keeping broken themes usable")
+                        .clicked()
+                    {
+                        self.settings.fixed_chrome = !self.settings.fixed_chrome
+                    };
+                    ui.menu_button(format!("Layout: {}", self.settings.layout), |ui| {
+                        for layout in SwatchLayout::ALL {
+                            if ui.button(layout.to_string()).clicked() {
+                                self.settings.layout = layout;
+                                ui.close_menu();
+                            }
+                        }
+                    });
                     let high2023 = self.data.high2023;
+                    let saturated = apply_space(
+                        self.data.model,
+                        &mut self.data.compute_lch(),
+                        Space::SRGB,
+                        high2023,
+                    );
                     ui.add_sized(
                         (150.0, 20.0),
                         ColorScale::new(
                             &mut self.data.high2023,
                             -1.0..=2.0,
                             0.1,
-                            format!("HIGH 2023 COMP {:.1}", high2023),
-                            colors[self.data.accent],
-                            colors[0],
+                            format!(
+                                "HIGH 2023 COMP {:.1}{}",
+                                high2023,
+                                if saturated { " MAX" } else { "" }
+                            ),
+                            chrome_fg,
+                            chrome_bg,
+                            15.0,
+                        ),
+                    );
+                    let alpha = self.data.alpha;
+                    ui.add_sized(
+                        (150.0, 20.0),
+                        ColorScale::new(
+                            &mut self.data.alpha,
+                            0.0..=1.0,
+                            0.01,
+                            format!("ALPHA {:.2}", alpha),
+                            chrome_fg,
+                            chrome_bg,
                             15.0,
                         ),
                     );
-                    Frame::none().fill(colors[0]).show(ui, |ui| {
+                    let desaturation = self.data.neutral_desaturation;
+                    ui.add_sized(
+                        (150.0, 20.0),
+                        ColorScale::new(
+                            &mut self.data.neutral_desaturation,
+                            0.0..=1.0,
+                            0.01,
+                            format!("NEUTRAL DESAT {:.2}", desaturation),
+                            chrome_fg,
+                            chrome_bg,
+                            15.0,
+                        ),
+                    )
+                    .on_hover_text("Pulls colors 0, 7, 8 and 15 towards gray");
+                    Frame::none().fill(chrome_bg).show(ui, |ui| {
                         ui.add_sized(
                             (300.0, 20.0),
                             Label::new(
                                 RichText::new("Collurgy Theme Creator 0.1.0")
                                     .size(15.0)
-                                    .color(colors[self.data.accent]),
+                                    .color(chrome_fg),
                             ),
                         )
                     });
@@ -445,61 +1473,93 @@ impl App for CollurgyUI {
                 ScrollArea::both().show(ui, |ui| {
                     ui.spacing_mut().item_spacing = (4.0 * s, 4.0 * s).into();
                     // LCH PICKERS {{{
+                    let picker = PickerOptions {
+                        font_size: 14.0 * s,
+                        scale: s * 2.0,
+                        space: self.data.model,
+                        high2023: self.data.high2023,
+                        gamut: self.data.gamut,
+                        clip: self.clip,
+                        smooth: self.settings.smooth,
+                        hue_snap: self.settings.hue_snap,
+                        chroma_lock: self.settings.chroma_lock,
+                        steps: self.settings.steps,
+                    };
                     ui.horizontal(|ui| {
                         ui.spacing_mut().item_spacing = (4.0 * s, 1.0 * s).into();
-                        ui.add(LCH::new(
-                            &mut self.data.foreground,
-                            "Foreground",
-                            colors[0],
-                            14.0 * s,
-                            s * 2.0,
-                            self.data.model,
-                            self.data.high2023,
-                            false,
-                            self.clip,
-                        ));
-                        ui.add(LCH::new(
-                            &mut self.data.background,
-                            "Background",
-                            colors[15],
-                            14.0 * s,
-                            s * 2.0,
-                            self.data.model,
-                            self.data.high2023,
-                            false,
-                            self.clip,
-                        ));
-                        ui.add(LCH::new(
-                            &mut self.data.spectrum,
-                            "Spectrum",
-                            colors[0],
-                            14.0 * s,
-                            s * 2.0,
-                            self.data.model,
-                            self.data.high2023,
-                            true,
-                            self.clip,
-                        ));
-                        ui.add(LCH::new(
-                            &mut self.data.spectrum_bright,
-                            "Spectrum Bright",
-                            colors[0],
-                            14.0 * s,
-                            s * 2.0,
-                            self.data.model,
-                            self.data.high2023,
-                            true,
-                            self.clip,
-                        ));
+                        ui.vertical(|ui| {
+                            ui.add(LCH::new(
+                                &mut self.data.foreground,
+                                &mut self.textures,
+                                "Foreground",
+                                colors[0],
+                                false,
+                                picker,
+                            ));
+                            if ui.small_button("Reset").clicked() {
+                                self.data.foreground = Collurgy::default().foreground
+                            }
+                        });
+                        ui.vertical(|ui| {
+                            ui.add(LCH::new(
+                                &mut self.data.background,
+                                &mut self.textures,
+                                "Background",
+                                colors[15],
+                                false,
+                                picker,
+                            ));
+                            if ui.small_button("Reset").clicked() {
+                                self.data.background = Collurgy::default().background
+                            }
+                        });
+                        let (hue, hue_bright) =
+                            (self.data.spectrum[2], self.data.spectrum_bright[2]);
+                        ui.vertical(|ui| {
+                            ui.add(LCH::new(
+                                &mut self.data.spectrum,
+                                &mut self.textures,
+                                "Spectrum",
+                                colors[0],
+                                true,
+                                picker,
+                            ));
+                            if ui.small_button("Reset").clicked() {
+                                self.data.spectrum = Collurgy::default().spectrum
+                            }
+                        });
+                        ui.vertical(|ui| {
+                            ui.add(LCH::new(
+                                &mut self.data.spectrum_bright,
+                                &mut self.textures,
+                                "Spectrum Bright",
+                                colors[0],
+                                true,
+                                picker,
+                            ));
+                            if ui.small_button("Reset").clicked() {
+                                self.data.spectrum_bright = Collurgy::default().spectrum_bright
+                            }
+                        });
+                        if self.link_hue {
+                            let delta = (self.data.spectrum[2] - hue)
+                                + (self.data.spectrum_bright[2] - hue_bright);
+                            if delta != 0.0 {
+                                self.data.spectrum[2] = (hue + delta).rem_euclid(360.0);
+                                self.data.spectrum_bright[2] =
+                                    (hue_bright + delta).rem_euclid(360.0);
+                            }
+                        }
                     });
                     // LCH PICKERS }}}
                     // COLOR BUTTONS {{{
-                    Grid::new("color_buttons")
-                        .spacing((4.0 * s, 4.0 * s))
-                        .show(ui, |ui| {
-                            for n in 0..16 {
-                                if ui
-                                    .add_sized(
+                    let grid =
+                        Grid::new("color_buttons")
+                            .spacing((4.0 * s, 4.0 * s))
+                            .show(ui, |ui| {
+                                let mut swatches = Vec::with_capacity(16);
+                                for n in 0..16 {
+                                    let response = ui.add_sized(
                                         (75.0 * s, 35.0 * s),
                                         ColorButton::new(
                                             format!("Color {}", n),
@@ -507,30 +1567,509 @@ impl App for CollurgyUI {
                                             if n == 0 { colors[15] } else { colors[0] },
                                             15.0 * s,
                                         ),
-                                    )
-                                    .clicked()
-                                {
-                                    self.data.accent = n
+                                    );
+                                    swatches.push(response.id);
+                                    let response = response.on_hover_ui(|ui| {
+                                        let (name, distance) = names::nearest(srgb[n]);
+                                        ui.label(format!(
+                                            "{} {} {}",
+                                            colcon::irgb_to_hex(srgb_to_irgb(srgb[n])),
+                                            if distance < names::TOLERANCE {
+                                                "≈"
+                                            } else {
+                                                "nearest"
+                                            },
+                                            name
+                                        ));
+                                    });
+                                    // clipped corner badge
+                                    if !in_gamut[n] {
+                                        let corner = response.rect.right_top();
+                                        ui.painter().add(egui::Shape::convex_polygon(
+                                            vec![
+                                                corner,
+                                                corner + egui::vec2(0.0, 10.0 * s),
+                                                corner - egui::vec2(10.0 * s, 0.0),
+                                            ],
+                                            Color32::RED,
+                                            Stroke::NONE,
+                                        ));
+                                    }
+                                    if n == self.data.accent {
+                                        ui.painter().rect_stroke(
+                                            response.rect.shrink(1.5 * s),
+                                            Rounding::ZERO,
+                                            Stroke::new(
+                                                3.0 * s,
+                                                if n == 0 { colors[0] } else { colors[15] },
+                                            ),
+                                        );
+                                    }
+                                    if self.data.overrides.contains_key(&n) {
+                                        ui.painter().circle_filled(
+                                            response.rect.left_top() + egui::vec2(6.0, 6.0) * s,
+                                            3.0 * s,
+                                            if n == 0 { colors[0] } else { colors[15] },
+                                        );
+                                    }
+                                    // a press dragged off the swatch shouldn't count,
+                                    // while Enter/Space on a focused one has no pointer
+                                    if response.clicked()
+                                        && (response.contains_pointer()
+                                            || !response.clicked_by(egui::PointerButton::Primary))
+                                    {
+                                        self.data.accent = n
+                                    };
+                                    // secondary accent role numbers
+                                    let roles: Vec<String> = (2..=self.data.accents.len() + 1)
+                                        .filter(|role| self.data.accent_role(*role) == n)
+                                        .map(|role| role.to_string())
+                                        .collect();
+                                    if !roles.is_empty() {
+                                        ui.painter().text(
+                                            response.rect.left_bottom() + egui::vec2(3.0, -3.0) * s,
+                                            Align2::LEFT_BOTTOM,
+                                            roles.join(","),
+                                            egui::FontId::monospace(10.0 * s),
+                                            if n == 0 { colors[15] } else { colors[0] },
+                                        );
+                                    }
+                                    let role_count = self.data.accents.len() + 2;
+                                    let role_count = match &self.output {
+                                        Output::Exporter(e) => {
+                                            role_count.max(self.exporters[e].accents)
+                                        }
+                                        _ => role_count,
+                                    };
+                                    response.clone().context_menu(|ui| {
+                                        ui.menu_button("Accent role", |ui| {
+                                            for role in 1..=role_count {
+                                                if ui.button(format!("Accent {}", role)).clicked()
+                                                {
+                                                    self.data.set_accent_role(role, n);
+                                                    ui.close_menu();
+                                                }
+                                            }
+                                            if !self.data.accents.is_empty()
+                                                && ui.button("Clear secondary").clicked()
+                                            {
+                                                self.data.accents.clear();
+                                                ui.close_menu();
+                                            }
+                                        });
+                                        ui.horizontal(|ui| {
+                                            ui.add(
+                                                egui::TextEdit::singleline(&mut self.pin_hex)
+                                                    .hint_text("#RRGGBB")
+                                                    .desired_width(70.0),
+                                            );
+                                            if ui.button("Pin to hex").clicked()
+                                                && parse_hex(&self.pin_hex).is_some()
+                                            {
+                                                self.data.overrides.insert(n, self.pin_hex.clone());
+                                                ui.close_menu();
+                                            }
+                                        });
+                                        ui.menu_button("Copy to slot…", |ui| {
+                                            // the theme's own color, not the blend preview
+                                            let hex = colcon::irgb_to_hex(srgb_to_irgb(
+                                                self.data.compute()[n],
+                                            ));
+                                            for target in (0..16).filter(|t| *t != n) {
+                                                if ui.button(format!("Color {}", target)).clicked() {
+                                                    self.data.overrides.insert(target, hex.clone());
+                                                    ui.close_menu();
+                                                }
+                                            }
+                                        });
+                                        if self.data.overrides.contains_key(&n)
+                                            && ui.button("Unpin").clicked()
+                                        {
+                                            self.data.overrides.remove(&n);
+                                            ui.close_menu();
+                                        }
+                                    });
+                                    if (n + 1) % self.settings.layout.columns() == 0 {
+                                        ui.end_row()
+                                    }
+                                }
+                                swatches
+                            });
+                    // arrow keys cycle the accent only while a swatch holds keyboard focus,
+                    // so they never fight a focused slider or text field
+                    if ui
+                        .memory(|m| m.focused())
+                        .is_some_and(|id| grid.inner.contains(&id))
+                    {
+                        let (left, right) = ui.input(|i| {
+                            (
+                                i.key_pressed(egui::Key::ArrowLeft),
+                                i.key_pressed(egui::Key::ArrowRight),
+                            )
+                        });
+                        if left {
+                            self.data.accent = (self.data.accent + 15) % 16
+                        }
+                        if right {
+                            self.data.accent = (self.data.accent + 1) % 16
+                        }
+                    }
+                    let duplicates = &self.analysis.duplicates;
+                    if !duplicates.is_empty() {
+                        ui.label(
+                            RichText::new(format!(
+                                "Nearly identical: {}",
+                                duplicates
+                                    .iter()
+                                    .map(|(a, b, d)| format!("{}/{} ΔE {:.3}", a, b, d))
+                                    .collect::<Vec<String>>()
+                                    .join(", ")
+                            ))
+                            .color(Color32::RED),
+                        );
+                    }
+                    // COLOR BUTTONS }}}
+                    // CONTRAST SOLVER {{{
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            DragValue::new(&mut self.contrast_target)
+                                .clamp_range(1.0..=21.0)
+                                .speed(0.05)
+                                .prefix("Min contrast "),
+                        );
+                        if ui.button("Solve contrast").clicked() {
+                            self.contrast_achieved = Some(
+                                self.data
+                                    .solve_contrast(&contrast::DEFAULT_PAIRS, self.contrast_target),
+                            );
+                        }
+                        if let Some(achieved) = self.contrast_achieved {
+                            ui.label(format!("Achieved {:.2}:1", achieved));
+                        }
+                    });
+                    // CONTRAST SOLVER }}}
+                    // PRIMARY ORDER {{{
+                    egui::CollapsingHeader::new("Primary order").show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            for (step, n) in self.data.primary_order.iter_mut().enumerate() {
+                                ui.add(
+                                    DragValue::new(n)
+                                        .clamp_range(1..=6)
+                                        .prefix(format!("{}° → ", step * 60)),
+                                );
+                            }
+                            if self.data.primaries() != self.data.primary_order {
+                                ui.label(
+                                    RichText::new("Not a permutation of 1-6, using default")
+                                        .color(Color32::RED),
+                                );
+                            }
+                            if ui.button("Reset").clicked() {
+                                self.data.primary_order = Collurgy::default().primary_order
+                            }
+                        });
+                    });
+                    // PRIMARY ORDER }}}
+                    // METRICS {{{
+                    egui::CollapsingHeader::new("Metrics").show(ui, |ui| {
+                        ui.add(
+                            DragValue::new(&mut self.settings.duplicate_threshold)
+                                .clamp_range(0.0..=0.2)
+                                .speed(0.001)
+                                .prefix("Duplicate ΔE under "),
+                        )
+                        .on_hover_text("Oklab distance under which two colors are flagged");
+                        let lch = self.analysis.lch;
+                        for (label, axis) in [("Lightness", 0), ("Chroma", 1)] {
+                            ui.horizontal(|ui| {
+                                ui.add_sized((70.0 * s, 40.0 * s), Label::new(label));
+                                for n in 0..16 {
+                                    let (rect, response) = ui.allocate_exact_size(
+                                        (10.0 * s, 40.0 * s).into(),
+                                        Sense::hover(),
+                                    );
+                                    let value = lch[n][axis];
+                                    let height = (value / 100.0).clamp(0.0, 1.0) * rect.height();
+                                    ui.painter().rect_filled(
+                                        egui::Rect::from_min_max(
+                                            (rect.left(), rect.bottom() - height).into(),
+                                            rect.right_bottom(),
+                                        ),
+                                        Rounding::ZERO,
+                                        colors[n],
+                                    );
+                                    response.on_hover_text(format!("Color {} {:.1}", n, value));
+                                }
+                            });
+                        }
+                        let [min, mean, max] = self.analysis.contrast;
+                        ui.label(format!(
+                            "Contrast against background and brights: min {:.2} mean {:.2} max {:.2}",
+                            min, mean, max
+                        ));
+                    });
+                    // METRICS }}}
+                    // PROBLEMS {{{
+                    let issues = &self.analysis.issues;
+                    egui::CollapsingHeader::new(format!("Problems ({})", issues.len()))
+                        .id_source("Problems")
+                        .show(ui, |ui| {
+                            if issues.is_empty() {
+                                ui.label("None");
+                            }
+                            for issue in issues.iter() {
+                                let color = match issue.severity {
+                                    Severity::Error => Color32::RED,
+                                    Severity::Warning => Color32::YELLOW,
                                 };
-                                if n == 7 {
-                                    ui.end_row()
+                                ui.label(RichText::new(issue.to_string()).color(color));
+                            }
+                        });
+                    // PROBLEMS }}}
+                    // BLEND {{{
+                    ui.horizontal(|ui| {
+                        ui.menu_button("Blend with", |ui| {
+                            let mut pick = None;
+                            for (n, tab) in self.tabs.iter().enumerate() {
+                                if n != self.tab && ui.button(&tab.data.name).clicked() {
+                                    pick = Some(tab.data.clone());
+                                    ui.close_menu();
                                 }
                             }
+                            if ui.button("File...").clicked() {
+                                let dialog = FileDialog::new()
+                                    .add_filter("Serialized Collurgy", &["toml", "json"]);
+                                pick = dialog
+                                    .pick_file()
+                                    .and_then(|path| read_to_string(path).ok())
+                                    .and_then(|string| parse_theme(&string).ok());
+                                ui.close_menu();
+                            }
+                            if pick.is_some() {
+                                self.blend = pick
+                            }
                         });
-                    // COLOR BUTTONS }}}
+                        if let Some(other) = &self.blend {
+                            let t = self.blend_t;
+                            let label = format!("BLEND {} {:.2}", other.name, t);
+                            ui.add_sized(
+                                (200.0 * s, 20.0 * s),
+                                ColorScale::new(
+                                    &mut self.blend_t,
+                                    0.0..=1.0,
+                                    0.01,
+                                    label,
+                                    chrome_fg,
+                                    chrome_bg,
+                                    15.0 * s,
+                                ),
+                            );
+                            if ui.button("Apply").clicked() {
+                                if let Some(blended) = &blended {
+                                    self.data = blended.clone()
+                                }
+                                self.blend = None
+                            }
+                            if ui.button("Cancel").clicked() {
+                                self.blend = None
+                            }
+                        }
+                    });
+                    // BLEND }}}
                     // LOREM IPSUM {{{
-                    for (fg, bg) in [
-                        (colors[15], colors[0]),
-                        (colors[7], colors[0]),
-                        (colors[15], colors[8]),
-                        (colors[7], colors[8]),
-                    ] {
-                        Frame::none().fill(bg).inner_margin(5.0 * s).show(ui, |ui| {
-                            ui.label(RichText::from(LI).color(fg).size(10.0 * s))
+                    for (fg, bg) in self.settings.preview_pairs.iter_mut() {
+                        ui.horizontal(|ui| {
+                            ui.label("Foreground");
+                            ui.add(DragValue::new(fg).clamp_range(0..=15));
+                            ui.label("Background");
+                            ui.add(DragValue::new(bg).clamp_range(0..=15));
+                            // stored settings may predate the clamp
+                            (*fg, *bg) = ((*fg).min(15), (*bg).min(15));
+                            ui.label(format!(
+                                "Contrast {:.2}",
+                                contrast::contrast(srgb[*fg], srgb[*bg])
+                            ));
                         });
+                        Frame::none()
+                            .fill(colors[*bg])
+                            .inner_margin(5.0 * s)
+                            .show(ui, |ui| {
+                                ui.label(RichText::from(LI).color(colors[*fg]).size(10.0 * s))
+                            });
                     }
                     // }}}
                 });
             });
+        self.auto_apply(ctx);
+        self.watch_source(ctx);
+        // RELOAD PROMPT {{{
+        if let (true, Some(path)) = (self.reload_prompt, self.source.clone()) {
+            let edited = self.data.content_hash() != self.source_hash;
+            egui::Window::new("Reload?")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(Align2::CENTER_CENTER, (0.0, 0.0))
+                .show(ctx, |ui| {
+                    ui.label(format!("{} changed on disk", path.display()));
+                    if edited {
+                        ui.label("Reloading will discard your unsaved edits");
+                    }
+                    ui.checkbox(&mut self.auto_reload, "Reload automatically when unedited");
+                    ui.horizontal(|ui| {
+                        if ui.button("Reload").clicked() {
+                            self.reload_source()
+                        }
+                        if ui.button("Ignore").clicked() {
+                            self.reload_prompt = false
+                        }
+                    });
+                });
+        }
+        // }}}
+        // COMMAND PALETTE {{{
+        if ctx.input(|i| i.modifiers.command && i.key_pressed(egui::Key::P)) {
+            self.palette = match self.palette {
+                Some(_) => None,
+                None => Some(String::new()),
+            }
+        }
+        if self.palette.is_some() {
+            let commands = self.commands();
+            let mut run = None;
+            let mut close = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+            let query = self.palette.as_deref().unwrap_or_default().to_lowercase();
+            let matches: Vec<usize> = commands
+                .iter()
+                .enumerate()
+                .filter(|(_, (name, _))| name.to_lowercase().contains(&query))
+                .map(|(n, _)| n)
+                .collect();
+            egui::Window::new("Commands")
+                .collapsible(false)
+                .resizable(false)
+                .title_bar(false)
+                .anchor(Align2::CENTER_TOP, (0.0, 40.0))
+                .show(ctx, |ui| {
+                    if let Some(query) = &mut self.palette {
+                        let edit = ui.add(
+                            egui::TextEdit::singleline(query)
+                                .hint_text("Type a command")
+                                .desired_width(300.0),
+                        );
+                        edit.request_focus();
+                        if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                            run = matches.first().copied();
+                        }
+                    }
+                    ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                        for n in matches.iter() {
+                            if ui.selectable_label(false, &commands[*n].0).clicked() {
+                                run = Some(*n)
+                            }
+                        }
+                    });
+                });
+            if let Some(n) = run {
+                if let Some((_, action)) = commands.into_iter().nth(n) {
+                    action(self, ctx)
+                }
+                close = true;
+            }
+            if close {
+                self.palette = None
+            }
+        }
+        // }}}
+        // CLOSE PROMPT {{{
+        if self.close_prompt {
+            egui::Window::new("Close tab?")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(Align2::CENTER_CENTER, (0.0, 0.0))
+                .show(ctx, |ui| {
+                    ui.label(format!("{} has unsaved edits", self.data.name));
+                    ui.horizontal(|ui| {
+                        if ui.button("Discard").clicked() {
+                            self.close_tab()
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.close_prompt = false
+                        }
+                    });
+                });
+        }
+        // }}}
+        // OVERWRITE PROMPT {{{
+        if let Some(save) = self.overwrite.clone() {
+            egui::Window::new("Overwrite?")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(Align2::CENTER_CENTER, (0.0, 0.0))
+                .show(ctx, |ui| {
+                    for path in save.existing() {
+                        ui.label(format!("{} already exists", path.display()));
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Overwrite").clicked() {
+                            self.write(save.clone());
+                            self.overwrite = None;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.overwrite = None;
+                        }
+                    });
+                });
+        }
+        // }}}
+        // TOASTS {{{
+        self.toasts
+            .retain(|(_, time)| time.elapsed() < Duration::from_secs(3));
+        if !self.toasts.is_empty() {
+            egui::Area::new(egui::Id::new("toasts"))
+                .anchor(Align2::RIGHT_BOTTOM, (-10.0, -10.0))
+                .show(ctx, |ui| {
+                    for (message, _) in self.toasts.iter() {
+                        Frame::none()
+                            .fill(chrome_bg)
+                            .stroke(Stroke::new(1.0, chrome_fg))
+                            .inner_margin(5.0)
+                            .show(ui, |ui| ui.label(RichText::new(message).color(chrome_fg)));
+                    }
+                });
+            ctx.request_repaint_after(Duration::from_millis(250));
+        }
+        // }}}
     } // }}}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::scale_from;
+
+    #[test]
+    fn scale_valid() {
+        assert_eq!(
+            scale_from(|key| (key == "COLLURGY_SCALE").then(|| "2".to_string())),
+            2.0
+        );
+    }
+
+    #[test]
+    fn scale_missing() {
+        assert_eq!(scale_from(|_| None), 1.0);
+    }
+
+    #[test]
+    fn scale_malformed() {
+        assert_eq!(scale_from(|_| Some("abc".to_string())), 1.0);
+        // a bad value falls through to the next variable
+        assert_eq!(
+            scale_from(|key| match key {
+                "COLLURGY_SCALE" => Some("abc".to_string()),
+                "GDK_SCALE" => Some("2".to_string()),
+                _ => None,
+            }),
+            2.0
+        );
+    }
+}
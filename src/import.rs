@@ -0,0 +1,159 @@
+use colcon::Space;
+
+use super::{parse_hex, unapply_space, Collurgy, CollurgyError};
+
+/// base16 slots of collurgy's spectrum rotation, red yellow green cyan blue magenta
+const BASE16_SPECTRUM: [&str; 6] = ["base08", "base0A", "base0B", "base0C", "base0D", "base0E"];
+
+/// `key: value` pairs of a flat or `palette:` nested YAML map, quotes and comments removed
+fn yaml_pairs(text: &str) -> impl Iterator<Item = (&str, &str)> {
+    text.lines().filter_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        let value = value.trim();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next()?,
+            _ => value.split(" #").next()?.trim(),
+        };
+        Some((key.trim().trim_matches(['"', '\'']), value))
+    })
+}
+
+/// Whether `text` has a top level or `palette:` nested `base00:` key
+pub fn is_base16(text: &str) -> bool {
+    yaml_pairs(text).any(|(key, _)| key.eq_ignore_ascii_case("base00"))
+}
+
+/// Fits a base16 scheme YAML into a theme.
+/// base00 and base05 become the background and foreground, and the six
+/// spectrum slots set the spectrum lightness, chroma, and best-fit hue rotation.
+/// base16 has no brights, so spectrum_bright is the spectrum 10 L lighter
+pub fn base16(text: &str) -> Result<Collurgy, CollurgyError> {
+    let mut result = Collurgy::default();
+    let mut slots = std::collections::HashMap::new();
+    for (key, value) in yaml_pairs(text) {
+        match key {
+            "scheme" | "name" if !value.is_empty() => result.name = value.to_string(),
+            _ => {
+                if let Some(rgb) = parse_hex(value) {
+                    slots.insert(key.to_ascii_lowercase(), rgb);
+                }
+            }
+        }
+    }
+    let slot = |name: &str| {
+        slots
+            .get(&name.to_ascii_lowercase())
+            .copied()
+            .ok_or_else(|| CollurgyError::Parse(format!("base16 scheme is missing {}", name)))
+    };
+
+    let mut neutrals = [slot("base00")?, slot("base05")?];
+    unapply_space(result.model, &mut neutrals, Space::SRGB);
+    (result.background, result.foreground) = (neutrals[0], neutrals[1]);
+
+    let mut spectrum = [[0.0; 3]; 6];
+    for (color, name) in spectrum.iter_mut().zip(BASE16_SPECTRUM) {
+        *color = slot(name)?
+    }
+    unapply_space(result.model, &mut spectrum, Space::SRGB);
+    let (mut l, mut c, mut sin, mut cos) = (0.0, 0.0, 0.0, 0.0);
+    for (n, [pl, pc, ph]) in spectrum.into_iter().enumerate() {
+        l += pl / 6.0;
+        c += pc / 6.0;
+        // each slot's hue with its rotation step removed, averaged on the circle
+        let h = (ph - 60.0 * n as f32).to_radians();
+        sin += h.sin();
+        cos += h.cos();
+    }
+    let h = sin.atan2(cos).to_degrees().rem_euclid(360.0);
+    result.spectrum = [l, c, h];
+    result.spectrum_bright = [(l + 10.0).min(100.0), c, h];
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use colcon::{irgb_to_hex, srgb_to_irgb};
+
+    /// Default Dark by Chris Kempson, in the original base16 layout
+    const DEFAULT_DARK: &str = r#"scheme: "Default Dark"
+author: "Chris Kempson (http://chriskempson.com)"
+base00: "181818"
+base01: "282828"
+base02: "383838"
+base03: "585858"
+base04: "b8b8b8"
+base05: "d8d8d8"
+base06: "e8e8e8"
+base07: "f8f8f8"
+base08: "ab4642"
+base09: "dc9656"
+base0A: "f7ca88"
+base0B: "a1b56c"
+base0C: "86c1b9"
+base0D: "7cafc2"
+base0E: "ba8baf"
+base0F: "a16946"
+"#;
+
+    /// The same scheme in the newer tinted-theming layout, unquoted with comments
+    const DEFAULT_DARK_PALETTE: &str = "system: base16
+name: Default Dark
+author: Chris Kempson (http://chriskempson.com)
+variant: dark
+palette:
+  base00: '#181818' # background
+  base01: '#282828'
+  base02: '#383838'
+  base03: '#585858'
+  base04: '#b8b8b8'
+  base05: '#d8d8d8' # foreground
+  base06: '#e8e8e8'
+  base07: '#f8f8f8'
+  base08: '#ab4642'
+  base09: '#dc9656'
+  base0A: '#f7ca88'
+  base0B: '#a1b56c'
+  base0C: '#86c1b9'
+  base0D: '#7cafc2'
+  base0E: '#ba8baf'
+  base0F: '#a16946'
+";
+
+    #[test]
+    fn pairs_unquote() {
+        let pairs: Vec<(&str, &str)> =
+            yaml_pairs("a: \"x: y\"\nb: 'z' # quoted\n\"c\": 123456 # bare\nd:\n  e: #abcdef")
+                .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("a", "x: y"),
+                ("b", "z"),
+                ("c", "123456"),
+                ("d", ""),
+                ("e", "#abcdef"),
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_base16() {
+        assert!(is_base16(DEFAULT_DARK));
+        assert!(is_base16(DEFAULT_DARK_PALETTE));
+        assert!(!is_base16("name = \"base00\"\nbroken ="));
+    }
+
+    #[test]
+    fn both_layouts_fit() {
+        for text in [DEFAULT_DARK, DEFAULT_DARK_PALETTE] {
+            let data = base16(text).unwrap();
+            assert_eq!(data.name, "Default Dark");
+            let colors = data.compute().map(|c| irgb_to_hex(srgb_to_irgb(c)));
+            assert_eq!(colors[0].to_lowercase(), "#181818");
+            assert_eq!(colors[15].to_lowercase(), "#d8d8d8");
+        }
+    }
+}
@@ -1,10 +1,31 @@
-use std::{collections::HashMap, ffi::OsStr, fs::read_to_string, path::PathBuf};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    ffi::OsStr,
+    fmt::Display,
+    fs::read_to_string,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 use colcon::{convert_space_chunked, irgb_to_hex, srgb_to_irgb, Space};
 use serde::{Deserialize, Serialize};
 
+mod builders;
+use builders::Builder;
+mod contrast;
+mod error;
+use error::CollurgyError;
+mod gamut;
+use gamut::Gamut;
 mod gui;
 use gui::CollurgyUI;
+mod import;
+mod names;
+mod presets;
+mod term;
+mod validate;
+use validate::Severity;
 
 #[derive(Serialize, Deserialize)]
 #[serde(remote = "Space")]
@@ -28,7 +49,18 @@ pub enum SpaceSerDe {
     JZAZBZ,
 }
 
-pub fn apply_space(space: Space, colors: &mut [[f32; 3]], to: colcon::Space, high2023: f32) {
+/// `Space` restricted to the serializable models
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Model(#[serde(with = "SpaceSerDe")] pub Space);
+
+/// Returns whether the high2023 compensation had to be clamped to the model's lightness range
+pub fn apply_space(
+    space: Space,
+    colors: &mut [[f32; 3]],
+    to: colcon::Space,
+    high2023: f32,
+) -> bool {
+    let mut clamped = false;
     // rescale to match SDR
     if space == Space::HSV {
         colors
@@ -40,18 +72,65 @@ pub fn apply_space(space: Space, colors: &mut [[f32; 3]], to: colcon::Space, hig
             p[1] = p[1] / 100.0 * space.srgb_quants()[95][1];
         });
         if high2023 != 0.0 {
+            let max = space.srgb_quants()[100][0];
             colors.iter_mut().for_each(|col| {
                 // seems like this actually kinda works?
                 col[0] += (space.srgb_quants()[100][0] * 0.2 - colcon::hk_high2023(col))
                     * (col[1] / space.srgb_quants()[95][1])
-                    * high2023
+                    * high2023;
+                if col[0] < 0.0 || col[0] > max {
+                    col[0] = col[0].clamp(0.0, max);
+                    clamped = true;
+                }
             });
         }
     };
     convert_space_chunked(space, to, colors);
+    clamped
 }
 
-#[derive(Serialize, Deserialize)]
+/// Inverse of `apply_space` without the high2023 compensation,
+/// taking `from` colors back to `space`'s 0-100 LCH ranges
+pub fn unapply_space(space: Space, colors: &mut [[f32; 3]], from: Space) {
+    convert_space_chunked(from, space, colors);
+    if space == Space::HSV {
+        colors
+            .iter_mut()
+            .for_each(|p| *p = [p[2] * 100.0, p[1] * 100.0, p[0] * 360.0]);
+    } else {
+        colors.iter_mut().for_each(|p| {
+            p[0] = p[0] / space.srgb_quants()[100][0] * 100.0;
+            p[1] = p[1] / space.srgb_quants()[95][1] * 100.0;
+            p[2] = p[2].rem_euclid(360.0);
+        });
+    }
+}
+
+fn default_alpha() -> f32 {
+    1.0
+}
+
+/// Red, Yellow, Green, Cyan, Blue, Magenta
+const DEFAULT_PRIMARIES: [usize; 6] = [1, 3, 2, 6, 4, 5];
+
+fn default_primaries() -> [usize; 6] {
+    DEFAULT_PRIMARIES
+}
+
+fn is_default_primaries(order: &[usize; 6]) -> bool {
+    *order == DEFAULT_PRIMARIES
+}
+
+/// Keeps untouched themes free of an `alpha = 1.0` line
+fn is_opaque(alpha: &f32) -> bool {
+    *alpha == 1.0
+}
+
+fn is_srgb(gamut: &Gamut) -> bool {
+    *gamut == Gamut::SRGB
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Collurgy {
     name: String,
     #[serde(with = "SpaceSerDe")]
@@ -60,6 +139,12 @@ pub struct Collurgy {
     /// High et al 2023 implementation.
     #[serde(default)]
     high2023: f32,
+    /// Opacity for exporters that support it
+    #[serde(default = "default_alpha", skip_serializing_if = "is_opaque")]
+    alpha: f32,
+    /// Gamut compute() clips to, and the pickers and {P3*} tokens show
+    #[serde(default, skip_serializing_if = "is_srgb")]
+    gamut: Gamut,
     /// LCH
     foreground: [f32; 3],
     /// LCH
@@ -70,29 +155,175 @@ pub struct Collurgy {
     spectrum_bright: [f32; 3],
     /// Which # should be accent
     accent: usize,
+    /// Further accent roles after `accent`, exported as {ACC2*} onwards
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    accents: Vec<usize>,
+    /// Indices the six 60° spectrum steps land on, starting from the spectrum hue
+    #[serde(
+        default = "default_primaries",
+        skip_serializing_if = "is_default_primaries"
+    )]
+    primary_order: [usize; 6],
+    /// Rotate the spectrum towards decreasing hue
+    #[serde(default)]
+    reverse_hue: bool,
+    /// Whether the {GRAY} ramp keeps the fg/bg chroma and hue
+    #[serde(default)]
+    tinted_grays: bool,
+    /// Index to hex, replacing the generated color
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", with = "index_map")]
+    overrides: HashMap<usize, String>,
+    /// Overridden primaries keep their hue and chroma but take the spectrum lightness
+    #[serde(default)]
+    equal_lightness: bool,
+    /// 0 ..= 1 fraction of chroma removed from the fg/bg derived 0, 7, 8, 15
+    #[serde(default)]
+    neutral_desaturation: f32,
     #[serde(default)]
     extras: HashMap<String, HashMap<String, usize>>,
 }
 
+/// TOML wants string keys
+mod index_map {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::{BTreeMap, HashMap};
+
+    pub fn serialize<S: Serializer>(
+        map: &HashMap<usize, String>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        map.iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect::<BTreeMap<String, &String>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<usize, String>, D::Error> {
+        Ok(BTreeMap::<String, String>::deserialize(deserializer)?
+            .into_iter()
+            .filter_map(|(k, v)| k.parse().ok().map(|k| (k, v)))
+            .collect())
+    }
+}
+
+/// LCH `a` towards `b` by `t`, the hue travelling the shorter arc
+fn mix_lch(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    let hue = (b[2] - a[2] + 540.0).rem_euclid(360.0) - 180.0;
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        (a[2] + hue * t).rem_euclid(360.0),
+    ]
+}
+
+/// `#RRGGBB` without the `#`, as the {*NHEX} tokens take it
+fn nhex(hex: &str) -> String {
+    hex.trim_start_matches('#').to_string()
+}
+
+/// `#RRGGBB` into SRGB
+pub fn parse_hex(hex: &str) -> Option<[f32; 3]> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let mut result = [0.0; 3];
+    for (n, c) in result.iter_mut().enumerate() {
+        *c = u8::from_str_radix(&hex[n * 2..n * 2 + 2], 16).ok()? as f32 / 255.0;
+    }
+    Some(result)
+}
+
 impl Default for Collurgy {
     fn default() -> Self {
         Self {
             name: String::from("Collurgy"),
             model: Space::OKLCH,
             high2023: 0.0,
+            alpha: 1.0,
+            gamut: Gamut::SRGB,
             foreground: [100.0, 0.0, 0.0],
             background: [0.0; 3],
             spectrum: [50.0, 50.0, 30.0],
             spectrum_bright: [70.0, 50.0, 30.0],
             accent: 11, // Bright Yellow
+            accents: Vec::new(),
+            primary_order: DEFAULT_PRIMARIES,
+            reverse_hue: false,
+            tinted_grays: false,
+            overrides: HashMap::new(),
+            equal_lightness: false,
+            neutral_desaturation: 0.0,
             extras: HashMap::new(),
         }
     }
 }
 
 impl Collurgy {
-    /// returns all 16 colors in SRGB
+    /// returns all 16 colors in SRGB, clipped to `gamut`
     fn compute(&self) -> [[f32; 3]; 16] {
+        self.compute_unclipped().map(|c| self.gamut.clip(c))
+    }
+
+    /// compute() before clipping, so out of gamut values can still be spotted
+    fn compute_unclipped(&self) -> [[f32; 3]; 16] {
+        let mut result = self.compute_lch();
+
+        apply_space(self.model, &mut result, Space::SRGB, self.high2023);
+
+        // pinned colors land on their exact hex unless their lightness was equalized
+        for (n, rgb) in self.pinned() {
+            if self.pinned_lightness(n).is_none() {
+                result[n] = rgb
+            }
+        }
+
+        result
+    }
+
+    /// Overrides that parse and land on one of the 16 colors, as SRGB
+    fn pinned(&self) -> impl Iterator<Item = (usize, [f32; 3])> + '_ {
+        self.overrides
+            .iter()
+            .filter(|(n, _)| **n < 16)
+            .filter_map(|(n, hex)| Some((*n, parse_hex(hex)?)))
+    }
+
+    /// Spectrum lightness a pinned primary takes when `equal_lightness` is set
+    fn pinned_lightness(&self, n: usize) -> Option<f32> {
+        match (self.equal_lightness, n) {
+            (true, 1..=6) => Some(self.spectrum[0]),
+            (true, 9..=14) => Some(self.spectrum_bright[0]),
+            _ => None,
+        }
+    }
+
+    /// compute() alongside whether each color landed inside the gamut before clipping
+    fn compute_checked(&self) -> ([[f32; 3]; 16], [bool; 16]) {
+        let colors = self.compute_unclipped();
+        let in_gamut = colors.map(|c| self.gamut.contains(&c, 1e-3));
+        (colors.map(|c| self.gamut.clip(c)), in_gamut)
+    }
+
+    /// returns all 16 colors as `model` LCH, before conversion.
+    /// Pinned colors are read back from their hex so they match the SRGB output
+    fn compute_lch(&self) -> [[f32; 3]; 16] {
+        let mut result = self.generate_lch();
+        for (n, rgb) in self.pinned() {
+            let mut lch = [rgb];
+            unapply_space(self.model, &mut lch, Space::SRGB);
+            if let Some(lightness) = self.pinned_lightness(n) {
+                lch[0][0] = lightness
+            }
+            result[n] = lch[0];
+        }
+        result
+    }
+
+    /// the 16 colors as generated from the parameters, as `model` LCH
+    fn generate_lch(&self) -> [[f32; 3]; 16] {
         let mut result = [[0.0; 3]; 16];
 
         result[0] = self.background;
@@ -113,70 +344,470 @@ impl Collurgy {
             .zip(self.background.as_slice().iter())
             .for_each(|(a, b)| *a = (*a * 2.0 + *b) / 3.0);
 
+        let step = if self.reverse_hue { -60.0 } else { 60.0 };
         let mut rots = (0..6).into_iter().map(|n| {
             [
                 self.spectrum[0],
                 self.spectrum[1],
-                60.0 * (n as f32) + self.spectrum[2],
+                (step * (n as f32) + self.spectrum[2]).rem_euclid(360.0),
             ]
         });
 
-        result[1] = rots.next().unwrap(); // Red
-        result[3] = rots.next().unwrap(); // Yellow
-        result[2] = rots.next().unwrap(); // Green
-        result[6] = rots.next().unwrap(); // Cyan
-        result[4] = rots.next().unwrap(); // Blue
-        result[5] = rots.next().unwrap(); // Magenta
+        let primaries = self.primaries();
+        for n in primaries {
+            result[n] = rots.next().unwrap();
+        }
 
         let mut brots = (0..6).into_iter().map(|n| {
             [
                 self.spectrum_bright[0],
                 self.spectrum_bright[1],
-                60.0 * (n as f32) + self.spectrum_bright[2],
+                (step * (n as f32) + self.spectrum_bright[2]).rem_euclid(360.0),
             ]
         });
 
-        result[9] = brots.next().unwrap(); // Red
-        result[11] = brots.next().unwrap(); // Yellow
-        result[10] = brots.next().unwrap(); // Green
-        result[14] = brots.next().unwrap(); // Cyan
-        result[12] = brots.next().unwrap(); // Blue
-        result[13] = brots.next().unwrap(); // Magenta
+        for n in primaries {
+            result[n + 8] = brots.next().unwrap();
+        }
 
-        apply_space(self.model, &mut result, Space::SRGB, self.high2023);
+        let keep = 1.0 - self.neutral_desaturation.clamp(0.0, 1.0);
+        for n in [0, 7, 8, 15] {
+            result[n][1] *= keep
+        }
+
+        result
+    }
+
+    /// `primary_order` if it's a permutation of 1..=6, otherwise the default
+    pub fn primaries(&self) -> [usize; 6] {
+        let mut sorted = self.primary_order;
+        sorted.sort();
+        if sorted == [1, 2, 3, 4, 5, 6] {
+            self.primary_order
+        } else {
+            DEFAULT_PRIMARIES
+        }
+    }
 
+    /// Fill missing exporter extras into data
+    fn fill_extras(&mut self, exporters: &HashMap<String, Exporter>) {
+        for (k, v) in exporters.iter() {
+            if let Some(extras) = &v.extras {
+                if !self.extras.contains_key(k) {
+                    self.extras.insert(k.to_string(), extras.clone());
+                }
+            }
+        }
+    }
+
+    /// Replace individual extras with the entries in `extras`, keyed by exporter then id.
+    /// The GUI lays its persisted edits over every theme it loads with this after
+    /// `fill_extras`, so an id takes the user's edit first, then the theme's own
+    /// value, then the exporter default
+    fn overlay_extras(&mut self, extras: &HashMap<String, HashMap<String, usize>>) {
+        for (exporter, ids) in extras.iter() {
+            let target = self.extras.entry(exporter.clone()).or_default();
+            for (id, n) in ids.iter() {
+                target.insert(id.clone(), *n);
+            }
+        }
+    }
+
+    /// The rendered colors rather than the parameters, as JSON hex strings
+    fn palette_json(&self, pretty: bool) -> String {
+        let hex = self.compute().map(|c| irgb_to_hex(srgb_to_irgb(c)));
+        let value = serde_json::json!({
+            "colors": hex,
+            "background": hex[0],
+            "foreground": hex[15],
+            "accent": hex[self.accent.min(15)],
+        });
+        if pretty {
+            serde_json::to_string_pretty(&value).unwrap()
+        } else {
+            value.to_string()
+        }
+    }
+
+    /// The 16 colors as `cell` pixel squares in a grid `cols` wide, as an RGBA8
+    /// buffer alongside its width and height. Cells past the 16th are transparent
+    pub fn render_swatches(&self, cell: u32, cols: u32) -> (Vec<u8>, u32, u32) {
+        let cols = cols.clamp(1, 16);
+        let rows = 16_u32.div_ceil(cols);
+        let (width, height) = (cell * cols, cell * rows);
+        let alpha = (self.alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let colors = self.compute().map(|c| srgb_to_irgb(c));
+        let mut buffer = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let n = (y / cell * cols + x / cell) as usize;
+                match colors.get(n) {
+                    Some([r, g, b]) => buffer.extend_from_slice(&[*r, *g, *b, alpha]),
+                    None => buffer.extend_from_slice(&[0; 4]),
+                }
+            }
+        }
+        (buffer, width, height)
+    }
+
+    /// Switches to `model`, carrying the stored colors over through SRGB
+    /// so the palette looks roughly the same afterwards
+    pub fn convert_model(&mut self, model: Space) {
+        let mut colors = [
+            self.foreground,
+            self.background,
+            self.spectrum,
+            self.spectrum_bright,
+        ];
+        apply_space(self.model, &mut colors, Space::SRGB, self.high2023);
+        unapply_space(model, &mut colors, Space::SRGB);
+        colors.iter_mut().flatten().for_each(|c| {
+            if c.is_nan() {
+                *c = 0.0
+            }
+        });
+        [
+            self.foreground,
+            self.background,
+            self.spectrum,
+            self.spectrum_bright,
+        ] = colors;
+        self.model = model;
+    }
+
+    /// For each named SRGB primary and secondary, the index whose hue
+    /// in the theme's model is closest. Near-gray colors are skipped
+    pub fn hue_names(&self) -> [(&'static str, usize); 6] {
+        const NAMES: [(&str, [f32; 3]); 6] = [
+            ("red", [1.0, 0.0, 0.0]),
+            ("yellow", [1.0, 1.0, 0.0]),
+            ("green", [0.0, 1.0, 0.0]),
+            ("cyan", [0.0, 1.0, 1.0]),
+            ("blue", [0.0, 0.0, 1.0]),
+            ("magenta", [1.0, 0.0, 1.0]),
+        ];
+        let mut targets = NAMES.map(|(_, c)| c);
+        unapply_space(self.model, &mut targets, Space::SRGB);
+        let mut colors = self.compute();
+        unapply_space(self.model, &mut colors, Space::SRGB);
+        let hue_distance = |a: f32, b: f32| {
+            let d = (a - b).rem_euclid(360.0);
+            d.min(360.0 - d)
+        };
+        let mut result = [("", 0); 6];
+        for (n, (name, _)) in NAMES.iter().enumerate() {
+            let nearest = (0..16)
+                .filter(|i| colors[*i][1] >= 5.0)
+                .min_by(|a, b| {
+                    hue_distance(colors[*a][2], targets[n][2])
+                        .total_cmp(&hue_distance(colors[*b][2], targets[n][2]))
+                })
+                .unwrap_or(self.accent_role(1));
+            result[n] = (*name, nearest);
+        }
+        result
+    }
+
+    /// Color `index`, overrides included, with its hue turned 180°, in SRGB clipped to `gamut`
+    pub fn complement_of(&self, index: usize) -> [f32; 3] {
+        let mut result = [self.compute()[index.min(15)]];
+        unapply_space(self.model, &mut result, Space::SRGB);
+        result[0][2] = (result[0][2] + 180.0).rem_euclid(360.0);
+        // the lightness read back already carries any high2023 compensation
+        apply_space(self.model, &mut result, Space::SRGB, 0.0);
+        self.gamut.clip(result[0])
+    }
+
+    /// Index for accent `role`, counting the primary accent as 1.
+    /// Indices past 15 fall back to the primary accent, or 15 for the primary itself
+    pub fn accent_role(&self, role: usize) -> usize {
+        let accent = self.accent.min(15);
+        match role {
+            0 | 1 => accent,
+            n => self
+                .accents
+                .get(n - 2)
+                .copied()
+                .filter(|n| *n < 16)
+                .unwrap_or(accent),
+        }
+    }
+
+    /// Sets accent `role` to color `n`, filling any skipped roles with the primary accent
+    pub fn set_accent_role(&mut self, role: usize, n: usize) {
+        if role <= 1 {
+            self.accent = n
+        } else {
+            if self.accents.len() < role - 1 {
+                self.accents.resize(role - 1, self.accent)
+            }
+            self.accents[role - 2] = n
+        }
+    }
+
+    /// Lightness-flipped copy for making a light variant of a dark theme or vice versa
+    pub fn inverted(&self) -> Collurgy {
+        let flip = |c: [f32; 3]| [100.0 - c[0], c[1], c[2]];
+        Collurgy {
+            foreground: flip(self.foreground),
+            background: flip(self.background),
+            spectrum: flip(self.spectrum),
+            spectrum_bright: flip(self.spectrum_bright),
+            ..self.clone()
+        }
+    }
+
+    /// TOML with the core fields first in a fixed order and commented,
+    /// then any other settings, then the tables
+    pub fn pretty_toml(&self) -> String {
+        const ORDER: [(&str, &str); 11] = [
+            ("name", ""),
+            ("model", "color model the LCH values are in"),
+            ("high2023", "Helmholtz-Kohlrausch compensation"),
+            ("alpha", "opacity"),
+            ("gamut", "display gamut target"),
+            ("foreground", "LCH"),
+            ("background", "LCH"),
+            ("spectrum", "LCH of the first spectrum color"),
+            ("spectrum_bright", "LCH of the first bright spectrum color"),
+            ("accent", "index"),
+            ("accents", "secondary accent indices"),
+        ];
+        let Ok(mut table) = toml::Table::try_from(self) else {
+            return toml::to_string(self).unwrap();
+        };
+        let mut result = String::new();
+        let mut line = |key: &str, value: &toml::Value, comment: &str| {
+            result += &format!("{} = {}", key, value);
+            if !comment.is_empty() {
+                result += &format!(" # {}", comment);
+            }
+            result.push('\n');
+        };
+        for (key, comment) in ORDER {
+            if let Some(value) = table.remove(key) {
+                line(key, &value, comment)
+            }
+        }
+        let mut tables = toml::Table::new();
+        for (key, value) in table {
+            if value.is_table() {
+                tables.insert(key, value);
+            } else {
+                line(&key, &value, "")
+            }
+        }
+        if !tables.is_empty() {
+            result.push('\n');
+            result += &toml::to_string(&tables).unwrap();
+        }
         result
     }
+
+    /// Hash of every field, for telling whether anything changed between frames.
+    /// Maps hash in sorted order so insertion order doesn't matter
+    pub fn content_hash(&self) -> u64 {
+        // destructured so a new field can't be forgotten here
+        let Collurgy {
+            name,
+            model,
+            high2023,
+            alpha,
+            gamut,
+            foreground,
+            background,
+            spectrum,
+            spectrum_bright,
+            accent,
+            accents,
+            primary_order,
+            reverse_hue,
+            tinted_grays,
+            overrides,
+            equal_lightness,
+            neutral_desaturation,
+            extras,
+        } = self;
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        std::mem::discriminant(model).hash(&mut hasher);
+        gamut.hash(&mut hasher);
+        for value in [*high2023, *alpha, *neutral_desaturation]
+            .iter()
+            .chain(foreground)
+            .chain(background)
+            .chain(spectrum)
+            .chain(spectrum_bright)
+        {
+            value.to_bits().hash(&mut hasher)
+        }
+        (accent, accents, primary_order).hash(&mut hasher);
+        (reverse_hue, tinted_grays, equal_lightness).hash(&mut hasher);
+        let mut overrides: Vec<(&usize, &String)> = overrides.iter().collect();
+        overrides.sort();
+        overrides.hash(&mut hasher);
+        let mut extras: Vec<(&String, Vec<(&String, &usize)>)> = extras
+            .iter()
+            .map(|(exporter, ids)| {
+                let mut ids: Vec<(&String, &usize)> = ids.iter().collect();
+                ids.sort();
+                (exporter, ids)
+            })
+            .collect();
+        extras.sort();
+        extras.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Interpolates the colors, high2023 and alpha towards `other`.
+    /// Hue travels the shorter arc. Everything else is kept from `self`
+    pub fn lerp(&self, other: &Collurgy, t: f32) -> Collurgy {
+        let mix = |a: [f32; 3], b: [f32; 3]| mix_lch(a, b, t);
+        Collurgy {
+            high2023: self.high2023 + (other.high2023 - self.high2023) * t,
+            alpha: self.alpha + (other.alpha - self.alpha) * t,
+            neutral_desaturation: self.neutral_desaturation
+                + (other.neutral_desaturation - self.neutral_desaturation) * t,
+            foreground: mix(self.foreground, other.foreground),
+            background: mix(self.background, other.background),
+            spectrum: mix(self.spectrum, other.spectrum),
+            spectrum_bright: mix(self.spectrum_bright, other.spectrum_bright),
+            ..self.clone()
+        }
+    }
+
+    /// returns a monotonic 8 stop ramp from background to foreground in SRGB, clipped to `gamut`
+    fn compute_grays(&self) -> [[f32; 3]; 8] {
+        let mut result = [[0.0; 3]; 8];
+
+        for (n, stop) in result.iter_mut().enumerate() {
+            *stop = mix_lch(self.background, self.foreground, n as f32 / 7.0);
+            if !self.tinted_grays {
+                (stop[1], stop[2]) = (0.0, 0.0)
+            }
+        }
+
+        apply_space(self.model, &mut result, Space::SRGB, self.high2023);
+
+        result.map(|c| self.gamut.clip(c))
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Exporter {
     name: String,
+    /// Optional when `generic` is set
+    #[serde(default)]
     formatter: String,
     path: Option<PathBuf>,
     extras: Option<HashMap<String, usize>>,
+    /// Model the theme's LCH values are read in, instead of the theme's own
+    model: Option<Model>,
+    /// How many accent roles the formatter uses, as {ACC1*} ..= {ACCn*}
+    #[serde(default)]
+    accents: usize,
+    /// End the output with exactly one newline
+    #[serde(default = "default_trailing_newline")]
+    trailing_newline: bool,
+    #[serde(default)]
+    line_ending: LineEnding,
+    /// Multiply every color token by the theme alpha, for formats that composite
+    /// premultiplied. {ALPHA} and {ALPHAHEX} are left as-is
+    #[serde(default)]
+    premultiply: bool,
+    /// Line rendered for every extras entry, sorted by key, in place of {EXTRAS}.
+    /// Takes {KEY}, {HEX}, {NHEX}, {R}, {G}, {B} and {INDEX}
+    generic: Option<String>,
+    /// Write the file in code instead of from `formatter`
+    builder: Option<Builder>,
+    /// File this was loaded from, None for builtins
+    #[serde(skip)]
+    source: Option<PathBuf>,
+    /// Replaced a builtin of the same name
+    #[serde(skip)]
+    shadows_builtin: bool,
+}
+
+fn default_trailing_newline() -> bool {
+    true
+}
+
+/// Newline style of the exported text
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    LF,
+    CRLF,
 }
 
 impl Exporter {
-    fn export(&self, data: &Collurgy) -> String {
-        let frgb = data.compute();
+    /// Whether the formatter would export nothing but whitespace
+    fn is_blank(&self) -> bool {
+        self.formatter.trim().is_empty() && self.generic.is_none() && self.builder.is_none()
+    }
+
+    fn export(&self, data: &Collurgy) -> Result<String, CollurgyError> {
+        // regenerated in the exporter's model, so every token comes from the same palette
+        let remodeled = self.model.map(|Model(model)| Collurgy {
+            model,
+            ..data.clone()
+        });
+        let data = remodeled.as_ref().unwrap_or(data);
+        let srgb = data.compute();
+        let lch = data.compute_lch();
+        let alpha = if self.premultiply {
+            data.alpha.clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let premultiply = |pixel: [f32; 3]| pixel.map(|c| c * alpha);
+        let frgb = srgb.map(premultiply);
         let irgb = frgb.map(|pixel| srgb_to_irgb(pixel));
         let hex = irgb.map(|pixel| irgb_to_hex(pixel));
+        let p3 = srgb.map(|pixel| premultiply(data.gamut.srgb_to_p3(pixel)));
+        let accent = data.accent_role(1);
+        let complement = irgb_to_hex(srgb_to_irgb(premultiply(data.complement_of(accent))));
         let mut result = self.formatter.clone();
         let mut swaps = irgb
             .iter()
             .zip(frgb.iter())
             .zip(hex.iter())
+            .zip(lch.iter())
+            .zip(p3.iter())
             .enumerate()
-            .map(|(n, ((ip, fp), hex))| {
+            .map(|(n, ((((ip, fp), hex), lp), pp))| {
                 vec![
                     (format!("{{R{}}}", n), ip[0].to_string()),
                     (format!("{{G{}}}", n), ip[1].to_string()),
                     (format!("{{B{}}}", n), ip[2].to_string()),
+                    // Windows COLORREF, 0x00BBGGRR
+                    (
+                        format!("{{DWORD{}}}", n),
+                        format!("{:08x}", u32::from_le_bytes([ip[0], ip[1], ip[2], 0])),
+                    ),
                     (format!("{{FR{}}}", n), fp[0].to_string()),
                     (format!("{{FG{}}}", n), fp[1].to_string()),
                     (format!("{{FB{}}}", n), fp[2].to_string()),
                     (format!("{{HEX{}}}", n), hex.clone()),
+                    (format!("{{NHEX{}}}", n), nhex(hex)),
+                    (format!("{{LCHL{}}}", n), lp[0].to_string()),
+                    (format!("{{LCHC{}}}", n), lp[1].to_string()),
+                    (
+                        format!("{{LCHH{}}}", n),
+                        lp[2].rem_euclid(360.0).to_string(),
+                    ),
+                    (
+                        format!("{{HRAD{}}}", n),
+                        lp[2].rem_euclid(360.0).to_radians().to_string(),
+                    ),
+                    (
+                        format!("{{HTURN{}}}", n),
+                        (lp[2].rem_euclid(360.0) / 360.0).to_string(),
+                    ),
+                    (format!("{{P3R{}}}", n), pp[0].to_string()),
+                    (format!("{{P3G{}}}", n), pp[1].to_string()),
+                    (format!("{{P3B{}}}", n), pp[2].to_string()),
                 ]
             })
             .reduce(|mut a, mut e| {
@@ -186,52 +817,272 @@ impl Exporter {
             .unwrap();
 
         swaps.append(&mut vec![
-            ("{ACCR}".to_string(), irgb[data.accent][0].to_string()),
-            ("{ACCG}".to_string(), irgb[data.accent][1].to_string()),
-            ("{ACCB}".to_string(), irgb[data.accent][2].to_string()),
-            ("{ACCFR}".to_string(), frgb[data.accent][0].to_string()),
-            ("{ACCFG}".to_string(), frgb[data.accent][1].to_string()),
-            ("{ACCFB}".to_string(), frgb[data.accent][2].to_string()),
-            ("{ACCHEX}".to_string(), hex[data.accent].clone()),
+            ("{ACCR}".to_string(), irgb[accent][0].to_string()),
+            ("{ACCG}".to_string(), irgb[accent][1].to_string()),
+            ("{ACCB}".to_string(), irgb[accent][2].to_string()),
+            ("{ACCFR}".to_string(), frgb[accent][0].to_string()),
+            ("{ACCFG}".to_string(), frgb[accent][1].to_string()),
+            ("{ACCFB}".to_string(), frgb[accent][2].to_string()),
+            ("{ACCHEX}".to_string(), hex[accent].clone()),
+            ("{ACCP3R}".to_string(), p3[accent][0].to_string()),
+            ("{ACCP3G}".to_string(), p3[accent][1].to_string()),
+            ("{ACCP3B}".to_string(), p3[accent][2].to_string()),
+            (
+                "{ACCHRAD}".to_string(),
+                lch[accent][2].rem_euclid(360.0).to_radians().to_string(),
+            ),
+            (
+                "{ACCHTURN}".to_string(),
+                (lch[accent][2].rem_euclid(360.0) / 360.0).to_string(),
+            ),
+            ("{ACCNHEX}".to_string(), nhex(&hex[accent])),
+            ("{ACCCOMPHEX}".to_string(), complement.clone()),
+            ("{ACCCOMPNHEX}".to_string(), nhex(&complement)),
+            ("{ALPHA}".to_string(), data.alpha.to_string()),
+            (
+                "{ALPHAHEX}".to_string(),
+                format!("{:02X}", (data.alpha.clamp(0.0, 1.0) * 255.0).round() as u8),
+            ),
             ("{NAME}".to_string(), data.name.clone()),
+            (
+                "{ISDARK}".to_string(),
+                if data.is_dark() { "dark" } else { "light" }.to_string(),
+            ),
         ]);
 
+        // {ACC1*} is the primary accent. Roles the theme doesn't set fall back to it
+        for role in 1..=self.accents.max(data.accents.len() + 1) {
+            let n = data.accent_role(role);
+            swaps.append(&mut vec![
+                (format!("{{ACC{}R}}", role), irgb[n][0].to_string()),
+                (format!("{{ACC{}G}}", role), irgb[n][1].to_string()),
+                (format!("{{ACC{}B}}", role), irgb[n][2].to_string()),
+                (format!("{{ACC{}FR}}", role), frgb[n][0].to_string()),
+                (format!("{{ACC{}FG}}", role), frgb[n][1].to_string()),
+                (format!("{{ACC{}FB}}", role), frgb[n][2].to_string()),
+                (format!("{{ACC{}HEX}}", role), hex[n].clone()),
+                (format!("{{ACC{}NHEX}}", role), nhex(&hex[n])),
+            ]);
+        }
+
+        for (name, n) in data.hue_names() {
+            swaps.push((format!("{{HUE:{}}}", name), hex[n].clone()));
+        }
+
+        for (n, gray) in data.compute_grays().into_iter().enumerate() {
+            swaps.push((
+                format!("{{GRAY{}}}", n),
+                irgb_to_hex(srgb_to_irgb(premultiply(gray))),
+            ));
+        }
+
+        let mut lines = Vec::new();
         if let Some(ext) = data.extras.get(&String::from(&self.name)) {
             for (id, n) in ext {
-                if let (Some(iv), Some(fv), Some(hv)) = (irgb.get(*n), frgb.get(*n), hex.get(*n)) {
-                    swaps.append(&mut vec![
-                        (format!("{{{}R}}", id), iv[0].to_string()),
-                        (format!("{{{}G}}", id), iv[1].to_string()),
-                        (format!("{{{}B}}", id), iv[2].to_string()),
-                        (format!("{{{}FR}}", id), fv[0].to_string()),
-                        (format!("{{{}FG}}", id), fv[1].to_string()),
-                        (format!("{{{}FB}}", id), fv[2].to_string()),
-                        (format!("{{{}HEX}}", id), hv.to_string()),
-                    ]);
+                let (Some(iv), Some(fv), Some(hv)) = (irgb.get(*n), frgb.get(*n), hex.get(*n))
+                else {
+                    return Err(CollurgyError::ExtraOutOfRange {
+                        exporter: self.name.clone(),
+                        id: id.clone(),
+                        index: *n,
+                    });
+                };
+                swaps.append(&mut vec![
+                    (format!("{{{}R}}", id), iv[0].to_string()),
+                    (format!("{{{}G}}", id), iv[1].to_string()),
+                    (format!("{{{}B}}", id), iv[2].to_string()),
+                    (format!("{{{}FR}}", id), fv[0].to_string()),
+                    (format!("{{{}FG}}", id), fv[1].to_string()),
+                    (format!("{{{}FB}}", id), fv[2].to_string()),
+                    (format!("{{{}HEX}}", id), hv.to_string()),
+                    (format!("{{{}NHEX}}", id), nhex(hv)),
+                    (format!("{{{}INDEX}}", id), n.to_string()),
+                ]);
+                if let Some(line) = &self.generic {
+                    lines.push((
+                        id,
+                        line.replace("{KEY}", id)
+                            .replace("{HEX}", hv)
+                            .replace("{NHEX}", &nhex(hv))
+                            .replace("{R}", &iv[0].to_string())
+                            .replace("{G}", &iv[1].to_string())
+                            .replace("{B}", &iv[2].to_string())
+                            .replace("{INDEX}", &n.to_string()),
+                    ))
                 }
             }
         }
 
-        for (a, b) in swaps {
-            result = result.replace(&a, &b)
+        if self.generic.is_some() {
+            lines.sort();
+            let lines: Vec<String> = lines.into_iter().map(|(_, line)| line).collect();
+            if result.contains("{EXTRAS}") {
+                result = result.replace("{EXTRAS}", &lines.join("\n"))
+            } else {
+                if !result.is_empty() && !result.ends_with('\n') {
+                    result.push('\n')
+                }
+                result += &lines.join("\n");
+            }
         }
-        result
+
+        if let Some(builder) = self.builder {
+            let mut extras = self.extras.clone().unwrap_or_default();
+            if let Some(ext) = data.extras.get(&self.name) {
+                extras.extend(ext.iter().map(|(id, n)| (id.clone(), *n)))
+            }
+            result = builder.build(&self.name, data, &hex, &extras)?
+        } else {
+            for (a, b) in swaps {
+                result = result.replace(&a, &b)
+            }
+        }
+
+        if self.trailing_newline {
+            result.truncate(result.trim_end_matches('\n').len());
+            result.push('\n');
+        }
+
+        if self.line_ending == LineEnding::CRLF {
+            result = result.replace("\r\n", "\n").replace('\n', "\r\n");
+        }
+
+        Ok(result)
+    }
+}
+
+pub enum Output {
+    Exporter(String),
+    JSON,
+    TOML,
+    Palette,
+}
+
+impl Display for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Output::Exporter(s) => f.write_fmt(format_args!("Export/{}", s)),
+            Output::JSON => f.write_str("Save/JSON"),
+            Output::TOML => f.write_str("Save/TOML"),
+            Output::Palette => f.write_str("Save/Palette"),
+        }
+    }
+}
+
+impl Output {
+    /// Accepts the displayed names with or without their Export/ Save/ prefix
+    fn parse(name: &str, exporters: &HashMap<String, Exporter>) -> Result<Self, CollurgyError> {
+        let name = name
+            .strip_prefix("Export/")
+            .or_else(|| name.strip_prefix("Save/"))
+            .unwrap_or(name);
+        match name {
+            "JSON" => Ok(Output::JSON),
+            "TOML" => Ok(Output::TOML),
+            "Palette" => Ok(Output::Palette),
+            name if exporters.contains_key(name) => Ok(Output::Exporter(name.to_string())),
+            _ => Err(CollurgyError::UnknownExporter(name.to_string())),
+        }
+    }
+
+    /// `pretty` only affects the JSON based outputs
+    fn render(
+        &self,
+        data: &Collurgy,
+        exporters: &HashMap<String, Exporter>,
+        pretty: bool,
+    ) -> Result<String, CollurgyError> {
+        match self {
+            Output::Exporter(s) => exporters
+                .get(s)
+                .ok_or_else(|| CollurgyError::UnknownExporter(s.clone()))?
+                .export(data),
+            Output::JSON if pretty => Ok(serde_json::to_string_pretty(data).unwrap()),
+            Output::JSON => Ok(serde_json::to_string(data).unwrap()),
+            Output::TOML => Ok(data.pretty_toml()),
+            Output::Palette => Ok(data.palette_json(pretty)),
+        }
+    }
+}
+
+/// `{TOKEN}` shaped substrings left in exporter output,
+/// usually from a typo or an extras key that didn't resolve
+pub fn unresolved_tokens(output: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut rest = output;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start..];
+        let end = rest[1..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == ':'))
+            .map(|n| n + 1)
+            .unwrap_or(rest.len());
+        if end > 1 && rest[end..].starts_with('}') {
+            result.push(rest[..=end].to_string());
+        }
+        rest = &rest[end..];
+    }
+    result
+}
+
+/// $XDG_CONFIG_HOME/collurgy, falling back to ~/.config/collurgy
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .map(|dir| dir.join("collurgy"))
+}
+
+/// Replaces a leading `~` with $HOME
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
     }
 }
 
+/// Exporters bundled into the binary, as (file, contents)
+#[cfg(feature = "builtins")]
+const BUILTINS: &[(&str, &str)] = &[
+    ("alacritty.toml", include_str!("../builtins/alacritty.toml")),
+    ("css.toml", include_str!("../builtins/css.toml")),
+    ("dunst.toml", include_str!("../builtins/dunst.toml")),
+    ("dwarf.toml", include_str!("../builtins/dwarf.toml")),
+    ("emacs.toml", include_str!("../builtins/emacs.toml")),
+    ("fish.toml", include_str!("../builtins/fish.toml")),
+    ("foot.toml", include_str!("../builtins/foot.toml")),
+    ("helix.toml", include_str!("../builtins/helix.toml")),
+    ("hyprland.toml", include_str!("../builtins/hyprland.toml")),
+    ("i3.toml", include_str!("../builtins/i3.toml")),
+    ("kde.toml", include_str!("../builtins/kde.toml")),
+    ("kitty.toml", include_str!("../builtins/kitty.toml")),
+    ("markdown.toml", include_str!("../builtins/markdown.toml")),
+    ("osc.toml", include_str!("../builtins/osc.toml")),
+    ("polybar.toml", include_str!("../builtins/polybar.toml")),
+    ("ppm.toml", include_str!("../builtins/ppm.toml")),
+    ("rofi.toml", include_str!("../builtins/rofi.toml")),
+    ("tmtheme.toml", include_str!("../builtins/tmtheme.toml")),
+    ("tmux.toml", include_str!("../builtins/tmux.toml")),
+    ("vim.toml", include_str!("../builtins/vim.toml")),
+    (
+        "windows-console.toml",
+        include_str!("../builtins/windows-console.toml"),
+    ),
+    ("vscode.toml", include_str!("../builtins/vscode.toml")),
+    ("waybar.toml", include_str!("../builtins/waybar.toml")),
+    ("zathura.toml", include_str!("../builtins/zathura.toml")),
+    (
+        "xresources.toml",
+        include_str!("../builtins/xresources.toml"),
+    ),
+];
+
 fn collect_exporters(paths: Vec<PathBuf>) -> HashMap<String, Exporter> {
     let mut result = HashMap::new();
     #[cfg(feature = "builtins")]
-    for builtin in [
-        include_str!("../builtins/dunst.toml"),
-        include_str!("../builtins/dwarf.toml"),
-        include_str!("../builtins/i3.toml"),
-        include_str!("../builtins/kitty.toml"),
-        include_str!("../builtins/ppm.toml"),
-        include_str!("../builtins/vim.toml"),
-        include_str!("../builtins/xresources.toml"),
-    ] {
-        let exporter = toml::from_str::<Exporter>(builtin).unwrap();
+    for (file, builtin) in BUILTINS {
+        let exporter = toml::from_str::<Exporter>(builtin)
+            .unwrap_or_else(|e| panic!("Malformed builtin exporter {}\n{}", file, e));
         result.insert(exporter.name.clone(), exporter);
     }
     let mut found = Vec::new();
@@ -241,46 +1092,622 @@ fn collect_exporters(paths: Vec<PathBuf>) -> HashMap<String, Exporter> {
                 for f in files.filter_map(|f| f.ok()) {
                     if f.path().extension() == Some(OsStr::new("toml")) {
                         if let Ok(s) = read_to_string(f.path()) {
-                            found.push(s)
+                            found.push((f.path(), s))
                         }
                     }
                 }
             }
         } else if p.extension() == Some(OsStr::new("toml")) {
-            if let Ok(s) = read_to_string(p) {
-                found.push(s)
+            if let Ok(s) = read_to_string(&p) {
+                found.push((p, s))
             }
         }
     }
-    for s in found.iter() {
-        if let Ok(exporter) = toml::from_str::<Exporter>(s.as_str()) {
+    for (path, s) in found.into_iter() {
+        if let Ok(mut exporter) = toml::from_str::<Exporter>(s.as_str()) {
+            exporter.shadows_builtin = result
+                .get(&exporter.name)
+                .is_some_and(|e| e.source.is_none() || e.shadows_builtin);
+            if exporter.shadows_builtin {
+                eprintln!(
+                    "{} overrides the builtin {} exporter",
+                    path.display(),
+                    exporter.name
+                );
+            }
+            exporter.source = Some(path);
             result.insert(exporter.name.clone(), exporter);
         }
     }
     result
 }
 
-fn main() {
-    let start = std::env::args()
-        .nth(1)
-        .map(|file| std::fs::read_to_string(file).ok())
-        .flatten()
-        .map(|string| toml::from_str(&string).ok())
-        .flatten()
-        .unwrap_or(Collurgy::default());
+/// `name` as a path, or failing that `name`.toml/json in the config themes dir
+fn resolve_theme(name: &str) -> PathBuf {
+    let path = PathBuf::from(name);
+    if path.exists() {
+        return path;
+    }
+    config_dir()
+        .map(|dir| dir.join("themes"))
+        .and_then(|dir| {
+            ["toml", "json", "yaml"]
+                .iter()
+                .map(|ext| dir.join(format!("{}.{}", name, ext)))
+                .find(|p| p.is_file())
+        })
+        .unwrap_or(path)
+}
+
+/// A serialized theme in either TOML or JSON, or a base16 scheme YAML to fit
+pub fn parse_theme(string: &str) -> Result<Collurgy, CollurgyError> {
+    toml::from_str(string)
+        .or_else(|e| serde_json::from_str(string).map_err(|_| e))
+        .map_err(|e| CollurgyError::Parse(e.to_string()))
+        .or_else(|e| {
+            if import::is_base16(string) {
+                import::base16(string)
+            } else {
+                Err(e)
+            }
+        })
+}
+
+/// Reads a serialized Collurgy, TOML or JSON, or a base16 scheme
+fn load_theme(name: &str) -> Result<Collurgy, CollurgyError> {
+    let path = resolve_theme(name);
+    let string = read_to_string(&path).map_err(|e| CollurgyError::Io(path.clone(), e))?;
+    parse_theme(&string).map_err(|e| match e {
+        CollurgyError::Parse(e) => CollurgyError::Parse(format!("{}: {}", path.display(), e)),
+        e => e,
+    })
+}
+
+fn main() -> ExitCode {
+    let mut theme = None;
+    let mut export = None;
+    let mut output_path = None;
+    let mut minify = false;
+    let mut preview = false;
+    let mut list_exporters = false;
+    let mut validate = false;
+    // later dirs override earlier ones by exporter name
+    let mut exporter_dirs = vec![PathBuf::from("./exporters/")];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--exporters-dir" => match args.next() {
+                Some(dir) => exporter_dirs.push(PathBuf::from(dir)),
+                None => {
+                    eprintln!("--exporters-dir requires a path");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--export" => match args.next() {
+                Some(name) => export = Some(name),
+                None => {
+                    eprintln!("--export requires an exporter name, JSON, TOML, or Palette");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--minify" => minify = true,
+            "--preview" => preview = true,
+            "--list-exporters" => list_exporters = true,
+            "validate" => validate = true,
+            "--output" => match args.next() {
+                Some(path) => output_path = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("--output requires a path");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ => theme = Some(arg),
+        }
+    }
+
+    let exporters = collect_exporters(exporter_dirs);
+
+    if list_exporters {
+        let mut names: Vec<&String> = exporters.keys().collect();
+        names.sort();
+        for name in names {
+            let exporter = &exporters[name];
+            match &exporter.source {
+                Some(path) if exporter.shadows_builtin => {
+                    println!("{}\t{} (overrides builtin)", name, path.display())
+                }
+                Some(path) => println!("{}\t{}", name, path.display()),
+                None => println!("{}\tbuiltin", name),
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    if validate {
+        let data = match theme.as_deref().map(load_theme) {
+            Some(Ok(data)) => data,
+            Some(Err(e)) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+            None => {
+                eprintln!("validate requires a theme");
+                return ExitCode::FAILURE;
+            }
+        };
+        let issues = data.validate(&exporters);
+        for issue in issues.iter() {
+            println!("{}", issue)
+        }
+        return if issues.iter().any(|i| i.severity == Severity::Error) {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        };
+    }
+
+    if preview {
+        match theme
+            .as_deref()
+            .map(load_theme)
+            .unwrap_or(Ok(Collurgy::default()))
+        {
+            Ok(data) => print!("{}", term::preview(&data)),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // headless
+    if let Some(name) = export {
+        let mut data = match theme.as_deref().map(load_theme) {
+            Some(Ok(data)) => data,
+            Some(Err(e)) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+            None => Collurgy::default(),
+        };
+        data.fill_extras(&exporters);
+        let text = match Output::parse(&name, &exporters).and_then(|output| {
+            output
+                .render(&data, &exporters, !minify)
+                .map(|t| (output, t))
+        }) {
+            Ok((Output::Exporter(exporter), text)) => {
+                if exporters[&exporter].is_blank() {
+                    eprintln!("Warning: {}", CollurgyError::EmptyFormatter(exporter));
+                }
+                let unresolved = unresolved_tokens(&text);
+                if !unresolved.is_empty() {
+                    eprintln!("Warning: {}", CollurgyError::InvalidToken(unresolved));
+                }
+                text
+            }
+            Ok((_, text)) => text,
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        match output_path {
+            Some(path) => {
+                if let Some(dir) = path.parent() {
+                    if let Err(e) = std::fs::create_dir_all(dir) {
+                        eprintln!("{}", CollurgyError::Io(dir.to_path_buf(), e));
+                        return ExitCode::FAILURE;
+                    }
+                }
+                if let Err(e) = std::fs::write(&path, text) {
+                    eprintln!("{}", CollurgyError::Io(path, e));
+                    return ExitCode::FAILURE;
+                }
+            }
+            None => print!("{}", text),
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // only watched for changes if it loaded
+    let (start, source) = match theme.as_deref().map(load_theme) {
+        Some(Ok(data)) => (data, theme.as_deref().map(resolve_theme)),
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            (Collurgy::default(), None)
+        }
+        None => (Collurgy::default(), None),
+    };
 
-    eframe::run_native(
+    match eframe::run_native(
         "Collurgy",
         eframe::NativeOptions {
             ..Default::default()
         },
-        Box::new(|cc| {
-            Box::new(CollurgyUI::new(
-                cc,
-                start,
-                collect_exporters(vec![PathBuf::from("./exporters/")]),
-            ))
-        }),
-    )
-    .unwrap();
+        Box::new(|cc| Box::new(CollurgyUI::new(cc, start, source, exporters))),
+    ) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "builtins")]
+    #[test]
+    fn builtins_export() {
+        let exporters = collect_exporters(Vec::new());
+        let mut data = Collurgy::default();
+        data.fill_extras(&exporters);
+        for (file, builtin) in BUILTINS {
+            let exporter = toml::from_str::<Exporter>(builtin)
+                .unwrap_or_else(|e| panic!("Malformed builtin exporter {}\n{}", file, e));
+            let output = exporter
+                .export(&data)
+                .unwrap_or_else(|e| panic!("{} failed to export\n{}", file, e));
+            let unresolved = unresolved_tokens(&output);
+            assert!(unresolved.is_empty(), "{} left {:?}", file, unresolved);
+        }
+    }
+
+    #[test]
+    fn opaque_alpha_not_serialized() {
+        let text = toml::to_string(&Collurgy::default()).unwrap();
+        let table: toml::Table = toml::from_str(&text).unwrap();
+        let mut keys: Vec<&str> = table.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "accent",
+                "background",
+                "equal_lightness",
+                "extras",
+                "foreground",
+                "high2023",
+                "model",
+                "name",
+                "neutral_desaturation",
+                "reverse_hue",
+                "spectrum",
+                "spectrum_bright",
+                "tinted_grays",
+            ]
+        );
+        assert_eq!(toml::from_str::<Collurgy>(&text).unwrap().alpha, 1.0);
+    }
+
+    #[test]
+    fn alpha_round_trips() {
+        let data = Collurgy {
+            alpha: 0.75,
+            ..Default::default()
+        };
+        let text = toml::to_string(&data).unwrap();
+        assert_eq!(toml::from_str::<Collurgy>(&text).unwrap().alpha, 0.75);
+    }
+
+    #[test]
+    fn unresolved_typos() {
+        let exporter: Exporter =
+            toml::from_str("name = \"Typos\"\nformatter = \"{HEX0} {HEXX0} { x } {NOPE}\"")
+                .unwrap();
+        let output = exporter.export(&Collurgy::default()).unwrap();
+        assert_eq!(unresolved_tokens(&output), ["{HEXX0}", "{NOPE}"]);
+    }
+
+    #[test]
+    fn emacs_name_escapes() {
+        let exporter: Exporter = toml::from_str(include_str!("../builtins/emacs.toml")).unwrap();
+        let data = Collurgy {
+            name: "C:\\ \"quoted\"\n(shell-command \"x\")".to_string(),
+            ..Default::default()
+        };
+        let output = exporter.export(&data).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            ";;; collurgy-theme.el --- C:\\ \"quoted\" (shell-command \"x\") -*- lexical-binding: t -*-"
+        );
+        assert_eq!(
+            lines[3],
+            r#"(deftheme collurgy "C:\\ \"quoted\"\n(shell-command \"x\")")"#
+        );
+        assert_eq!(output.matches('(').count(), output.matches(')').count());
+    }
+
+    #[test]
+    fn helix_diagnostics_follow_extras() {
+        let exporter: Exporter = toml::from_str(include_str!("../builtins/helix.toml")).unwrap();
+        let mut data = Collurgy {
+            name: "two\nlines".to_string(),
+            ..Default::default()
+        };
+        data.extras.insert(
+            exporter.name.clone(),
+            HashMap::from([("ERROR".to_string(), 9)]),
+        );
+        let theme: toml::Table = toml::from_str(&exporter.export(&data).unwrap()).unwrap();
+        let underline = |scope: &str| theme[scope]["underline"]["color"].as_str();
+        assert_eq!(underline("diagnostic.error"), Some("color9"));
+        assert_eq!(underline("diagnostic.warning"), Some("color3"));
+    }
+
+    #[test]
+    fn builders_reject_bad_extras() {
+        for builtin in [
+            include_str!("../builtins/alacritty.toml"),
+            include_str!("../builtins/helix.toml"),
+            include_str!("../builtins/vscode.toml"),
+        ] {
+            let mut exporter: Exporter = toml::from_str(builtin).unwrap();
+            let id = exporter
+                .extras
+                .as_ref()
+                .unwrap()
+                .keys()
+                .min()
+                .unwrap()
+                .clone();
+            let mut data = Collurgy::default();
+            data.extras
+                .insert(exporter.name.clone(), HashMap::from([(id.clone(), 16)]));
+            assert!(matches!(
+                exporter.export(&data),
+                Err(CollurgyError::ExtraOutOfRange { index: 16, .. })
+            ));
+            exporter.extras.as_mut().unwrap().remove(&id);
+            assert!(matches!(
+                exporter.export(&Collurgy::default()),
+                Err(CollurgyError::InvalidToken(tokens)) if tokens == [format!("{{{}HEX}}", id)]
+            ));
+        }
+    }
+
+    #[test]
+    fn tmtheme_name_escapes() {
+        let exporter: Exporter = toml::from_str(include_str!("../builtins/tmtheme.toml")).unwrap();
+        let data = Collurgy {
+            name: "Tom & \"Jerry\" <3</string>\u{1b}".to_string(),
+            ..Default::default()
+        };
+        let output = exporter.export(&data).unwrap();
+        assert!(
+            output.contains("<string>Tom &amp; &quot;Jerry&quot; &lt;3&lt;/string&gt;</string>")
+        );
+        assert_eq!(
+            output.matches("<dict>").count(),
+            output.matches("</dict>").count()
+        );
+        assert!(unresolved_tokens(&output).is_empty());
+    }
+
+    #[test]
+    fn vscode_name_escapes() {
+        let exporter: Exporter = toml::from_str(include_str!("../builtins/vscode.toml")).unwrap();
+        let data = Collurgy {
+            name: r#"C:\ "quoted""#.to_string(),
+            ..Default::default()
+        };
+        let theme: serde_json::Value =
+            serde_json::from_str(&exporter.export(&data).unwrap()).unwrap();
+        assert_eq!(theme["name"], data.name.as_str());
+    }
+
+    #[test]
+    fn content_hash_ignores_map_order() {
+        let (mut a, mut b) = (Collurgy::default(), Collurgy::default());
+        let ids = ["A", "B", "C", "D", "E", "F", "G", "H"];
+        let exporters = ["One", "Two"];
+        for (n, id) in ids.iter().enumerate() {
+            a.overrides.insert(n, format!("#0000{:02x}", n));
+            b.overrides.insert(7 - n, format!("#0000{:02x}", 7 - n));
+            for (e, exporter) in exporters.iter().enumerate() {
+                a.extras
+                    .entry(exporter.to_string())
+                    .or_default()
+                    .insert(id.to_string(), n);
+                b.extras
+                    .entry(exporters[1 - e].to_string())
+                    .or_default()
+                    .insert(ids[7 - n].to_string(), 7 - n);
+            }
+        }
+        assert_eq!(a.content_hash(), b.content_hash());
+        b.spectrum[1] += 1.0;
+        assert_ne!(a.content_hash(), b.content_hash());
+    }
+
+    #[test]
+    fn content_hash_covers_fields() {
+        let base = Collurgy::default();
+        let edits: [fn(&mut Collurgy); 18] = [
+            |d| d.name.push('x'),
+            |d| d.model = Space::CIELCH,
+            |d| d.high2023 = 1.0,
+            |d| d.alpha = 0.5,
+            |d| d.gamut = Gamut::P3,
+            |d| d.foreground[0] = 90.0,
+            |d| d.background[1] = 5.0,
+            |d| d.spectrum[2] = 40.0,
+            |d| d.spectrum_bright[0] = 60.0,
+            |d| d.accent = 3,
+            |d| d.accents.push(4),
+            |d| d.primary_order.swap(0, 1),
+            |d| d.reverse_hue = true,
+            |d| d.tinted_grays = true,
+            |d| {
+                d.overrides.insert(1, "#123456".to_string());
+            },
+            |d| d.equal_lightness = true,
+            |d| d.neutral_desaturation = 0.5,
+            |d| {
+                d.extras.insert("Test".to_string(), HashMap::new());
+            },
+        ];
+        let mut hashes = vec![base.content_hash()];
+        for edit in edits {
+            let mut data = base.clone();
+            edit(&mut data);
+            hashes.push(data.content_hash());
+        }
+        hashes.sort();
+        hashes.dedup();
+        assert_eq!(hashes.len(), edits.len() + 1);
+        assert_eq!(base.content_hash(), base.clone().content_hash());
+    }
+
+    #[test]
+    fn high2023_clamps_lightness() {
+        let mut colors = [
+            [100.0, 100.0, 30.0],
+            [100.0, 100.0, 250.0],
+            [0.0, 100.0, 30.0],
+            [0.0, 100.0, 250.0],
+        ];
+        assert!(apply_space(Space::OKLCH, &mut colors, Space::OKLCH, 2.0));
+        let max = Space::OKLCH.srgb_quants()[100][0];
+        for color in colors {
+            assert!(color.iter().all(|c| c.is_finite()), "{:?}", color);
+            assert!((0.0..=max).contains(&color[0]), "{:?}", color);
+        }
+    }
+
+    #[test]
+    fn hue_mix_takes_short_arc() {
+        let [_, _, h] = mix_lch([20.0, 10.0, 350.0], [80.0, 10.0, 30.0], 0.25);
+        assert!((h - 0.0).abs() < 1e-3 || (h - 360.0).abs() < 1e-3, "{}", h);
+        let [_, _, h] = mix_lch([20.0, 10.0, 30.0], [80.0, 10.0, 350.0], 0.5);
+        assert!((h - 10.0).abs() < 1e-3, "{}", h);
+    }
+
+    #[test]
+    fn lch_tokens_follow_overrides() {
+        let exporter: Exporter =
+            toml::from_str("name = \"Lch\"\nformatter = \"{LCHL1} {LCHC1} {LCHH1}\"").unwrap();
+        let mut data = Collurgy::default();
+        data.overrides.insert(1, "#123456".to_string());
+        let output = exporter.export(&data).unwrap();
+        let values: Vec<f32> = output
+            .split_whitespace()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        let mut color = [[values[0], values[1], values[2]]];
+        apply_space(data.model, &mut color, Space::SRGB, 0.0);
+        assert_eq!(irgb_to_hex(srgb_to_irgb(color[0])), "#123456");
+    }
+
+    #[test]
+    fn compute_clips_to_gamut() {
+        let mut data = Collurgy {
+            spectrum: [60.0, 150.0, 30.0],
+            ..Default::default()
+        };
+        let (srgb, in_gamut) = data.compute_checked();
+        assert!(in_gamut.contains(&false));
+        assert!(srgb.iter().flatten().all(|c| (0.0..=1.0).contains(c)));
+        data.gamut = Gamut::P3;
+        let p3 = data.compute();
+        assert!(p3.iter().all(|c| Gamut::P3.contains(c, 1e-4)), "{:?}", p3);
+        assert!(p3.iter().flatten().any(|c| !(0.0..=1.0).contains(c)));
+    }
+
+    #[test]
+    fn dword_is_bgr() {
+        let exporter: Exporter =
+            toml::from_str("name = \"Reg\"\nformatter = \"{DWORD1}\"").unwrap();
+        let mut data = Collurgy::default();
+        data.overrides.insert(1, "#123456".to_string());
+        assert_eq!(exporter.export(&data).unwrap(), "00563412\n");
+    }
+
+    #[test]
+    fn complement_follows_overrides() {
+        let mut data = Collurgy::default();
+        data.overrides.insert(data.accent, "#ff0000".to_string());
+        let [r, g, b] = data.complement_of(data.accent);
+        assert!(g > r && b > r, "{:?}", [r, g, b]);
+    }
+
+    #[test]
+    fn swatch_image_size() {
+        let data = Collurgy::default();
+        for (cell, cols, rows) in [(32, 8, 2), (10, 16, 1), (4, 5, 4), (3, 0, 16)] {
+            let (bytes, width, height) = data.render_swatches(cell, cols);
+            let cols = cols.clamp(1, 16);
+            assert_eq!((width, height), (cell * cols, cell * rows));
+            assert_eq!(bytes.len(), (width * height * 4) as usize);
+        }
+    }
+
+    #[test]
+    fn blank_formatter_warns() {
+        let mut exporter: Exporter =
+            toml::from_str("name = \"Blank\"\nformatter = \" \\n\"").unwrap();
+        let mut data = Collurgy::default();
+        // warned about, but whatever it produced is still returned
+        assert!(exporter.is_blank());
+        assert_eq!(exporter.export(&data).unwrap(), " \n");
+        // generic exporters need no formatter
+        exporter.formatter.clear();
+        exporter.generic = Some("{KEY}={HEX}".to_string());
+        assert!(!exporter.is_blank());
+        data.extras.insert(
+            exporter.name.clone(),
+            HashMap::from([("BG".to_string(), 0)]),
+        );
+        assert_eq!(exporter.export(&data).unwrap(), "BG=#000000\n");
+    }
+
+    #[test]
+    fn model_override_regenerates() {
+        let mut exporter: Exporter = toml::from_str("name = \"Model\"").unwrap();
+        exporter.formatter = (0..16)
+            .map(|n| format!("{{HEX{0}}} {{LCHL{0}}} {{LCHC{0}}} {{LCHH{0}}}", n))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let mut data = Collurgy::default();
+        data.overrides.insert(1, "#123456".to_string());
+        let native = exporter.export(&data).unwrap();
+        exporter.model = Some(Model(data.model));
+        assert_eq!(exporter.export(&data).unwrap(), native);
+        for model in [Space::CIELCH, Space::JZCZHZ] {
+            exporter.model = Some(Model(model));
+            let output = exporter.export(&data).unwrap();
+            assert_ne!(output, native);
+            assert_eq!(
+                output,
+                exporter
+                    .export(&Collurgy {
+                        model,
+                        ..data.clone()
+                    })
+                    .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn literal_braces_resolve() {
+        let mut data = Collurgy::default();
+        for builtin in [
+            include_str!("../builtins/css.toml"),
+            include_str!("../builtins/rofi.toml"),
+        ] {
+            let exporter: Exporter = toml::from_str(builtin).unwrap();
+            if let Some(extras) = &exporter.extras {
+                data.extras.insert(exporter.name.clone(), extras.clone());
+            }
+            let output = exporter.export(&data).unwrap();
+            assert!(unresolved_tokens(&output).is_empty(), "{}", exporter.name);
+        }
+    }
 }
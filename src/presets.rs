@@ -0,0 +1,40 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use super::{config_dir, Collurgy};
+
+/// returns the bundled example themes as editable starting points
+pub fn presets() -> Vec<Collurgy> {
+    [
+        include_str!("../examples/ansi.toml"),
+        include_str!("../examples/bsz.toml"),
+        include_str!("../examples/gruvbox.toml"),
+        include_str!("../examples/nord.toml"),
+        include_str!("../examples/solarized.toml"),
+    ]
+    .into_iter()
+    .map(|preset| toml::from_str::<Collurgy>(preset).unwrap())
+    .collect()
+}
+
+fn user_presets_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("presets.toml"))
+}
+
+/// named user presets from the config dir. Missing or malformed stores are empty
+pub fn load_user_presets() -> BTreeMap<String, Collurgy> {
+    user_presets_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|s| toml::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_user_presets(presets: &BTreeMap<String, Collurgy>) -> std::io::Result<()> {
+    let path = user_presets_path().ok_or(std::io::ErrorKind::NotFound)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(
+        path,
+        toml::to_string(presets).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?,
+    )
+}
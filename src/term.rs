@@ -0,0 +1,79 @@
+use colcon::{convert_space_chunked, srgb_to_irgb, Space};
+
+use super::{contrast::oklab_distance, Collurgy};
+
+/// Whether $COLORTERM advertises 24-bit color
+pub fn truecolor() -> bool {
+    std::env::var("COLORTERM").is_ok_and(|v| v == "truecolor" || v == "24bit")
+}
+
+/// The xterm-256 color cube and grayscale ramp as SRGB, indexed from 16
+fn xterm_palette() -> Vec<[f32; 3]> {
+    const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let mut result: Vec<[f32; 3]> = Vec::with_capacity(240);
+    for r in CUBE {
+        for g in CUBE {
+            for b in CUBE {
+                result.push([r, g, b].map(|c| c as f32 / 255.0))
+            }
+        }
+    }
+    for n in 0..24 {
+        result.push([(8 + n * 10) as f32 / 255.0; 3])
+    }
+    result
+}
+
+/// Nearest xterm-256 index to an SRGB color by Oklab distance.
+/// Skips 0-15 since those are whatever the terminal's current theme is
+pub fn xterm256(srgb: [f32; 3]) -> u8 {
+    let mut palette = xterm_palette();
+    let mut target = [srgb];
+    convert_space_chunked(Space::SRGB, Space::OKLAB, &mut palette);
+    convert_space_chunked(Space::SRGB, Space::OKLAB, &mut target);
+    let distance = |c: &[f32; 3]| oklab_distance(c, &target[0]);
+    palette
+        .iter()
+        .enumerate()
+        .min_by(|a, b| distance(a.1).total_cmp(&distance(b.1)))
+        .map(|(n, _)| n as u8 + 16)
+        .unwrap_or(16)
+}
+
+/// SGR escape setting the background, or foreground when `fg`
+pub fn escape(srgb: [f32; 3], fg: bool, truecolor: bool) -> String {
+    let layer = if fg { 38 } else { 48 };
+    if truecolor {
+        let [r, g, b] = srgb_to_irgb(srgb);
+        format!("\x1b[{};2;{};{};{}m", layer, r, g, b)
+    } else {
+        format!("\x1b[{};5;{}m", layer, xterm256(srgb))
+    }
+}
+
+/// Swatches of all 16 colors in two rows, for printing straight to the terminal
+pub fn preview(data: &Collurgy) -> String {
+    let truecolor = truecolor();
+    let colors = data.compute();
+    let mut result = String::new();
+    for row in colors.chunks(8).zip([0, 8]) {
+        for (n, color) in row.0.iter().enumerate() {
+            let text = if row.1 + n == 0 {
+                colors[15]
+            } else {
+                colors[0]
+            };
+            result += &escape(*color, false, truecolor);
+            result += &escape(text, true, truecolor);
+            result += &format!(" {:>2} ", row.1 + n);
+        }
+        result += "\x1b[0m\n";
+    }
+    for (fg, bg) in [(15, 0), (7, 0), (data.accent, 0)] {
+        result += &escape(colors[bg], false, truecolor);
+        result += &escape(colors[fg], true, truecolor);
+        result += &format!(" {} ", data.name);
+        result += "\x1b[0m\n";
+    }
+    result
+}
@@ -0,0 +1,189 @@
+use std::{collections::HashMap, fmt::Display};
+
+use super::{contrast, parse_hex, Collurgy, CollurgyError, Exporter};
+
+/// WCAG AA for body text
+const MIN_CONTRAST: f32 = 4.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Exports, but probably not as intended
+    Warning,
+    /// Exports will fail or produce garbage
+    Error,
+}
+
+/// One problem found by [`Collurgy::validate`]
+#[derive(Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Issue {
+    fn warning(message: impl ToString) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.to_string(),
+        }
+    }
+
+    fn error(message: impl ToString) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+impl Collurgy {
+    /// Every problem with the theme, errors first
+    pub fn validate(&self, exporters: &HashMap<String, Exporter>) -> Vec<Issue> {
+        let mut result = Vec::new();
+
+        for (name, value) in [
+            ("foreground", self.foreground),
+            ("background", self.background),
+            ("spectrum", self.spectrum),
+            ("spectrum_bright", self.spectrum_bright),
+            ("high2023/alpha", [self.high2023, self.alpha, 0.0]),
+        ] {
+            if value.iter().any(|c| !c.is_finite()) {
+                result.push(Issue::error(format!("{} is not a number", name)))
+            }
+        }
+
+        if self.accent > 15 {
+            result.push(Issue::error(format!("Accent {} is past 15", self.accent)))
+        }
+        for (n, index) in self.accents.iter().enumerate().filter(|(_, i)| **i > 15) {
+            result.push(Issue::error(format!(
+                "Accent role {} is color {}, which is past 15",
+                n + 2,
+                index
+            )))
+        }
+        if self.primaries() != self.primary_order {
+            result.push(Issue::error(format!(
+                "Primary order {:?} isn't a permutation of 1-6, so the default is used",
+                self.primary_order
+            )))
+        }
+        let mut overrides: Vec<(&usize, &String)> = self.overrides.iter().collect();
+        overrides.sort();
+        for (n, hex) in overrides {
+            if *n > 15 {
+                result.push(Issue::error(format!("Override for color {} is past 15", n)))
+            } else if parse_hex(hex).is_none() {
+                result.push(Issue::error(format!(
+                    "Override {:?} for color {} isn't a #RRGGBB hex",
+                    hex, n
+                )))
+            }
+        }
+
+        let mut filled = self.clone();
+        filled.fill_extras(exporters);
+        let mut extras: Vec<(&String, &String, usize)> = filled
+            .extras
+            .iter()
+            .flat_map(|(exporter, ids)| ids.iter().map(move |(id, n)| (exporter, id, *n)))
+            .filter(|(_, _, n)| *n > 15)
+            .collect();
+        extras.sort();
+        for (exporter, id, index) in extras {
+            result.push(Issue::error(CollurgyError::ExtraOutOfRange {
+                exporter: exporter.clone(),
+                id: id.clone(),
+                index,
+            }))
+        }
+
+        let (colors, in_gamut) = self.compute_checked();
+        for n in (0..16).filter(|n| !in_gamut[*n]) {
+            result.push(Issue::warning(format!(
+                "Color {} is outside {:?} and will be clipped",
+                n, self.gamut
+            )))
+        }
+
+        for (a, b, distance) in self.similar_pairs(contrast::DUPLICATE_THRESHOLD) {
+            result.push(Issue::warning(format!(
+                "Colors {} and {} are nearly identical, ΔE {:.3}",
+                a, b, distance
+            )))
+        }
+
+        let ratio = contrast::contrast(colors[15], colors[0]);
+        if ratio < MIN_CONTRAST {
+            result.push(Issue::warning(format!(
+                "Foreground on background contrast is {:.2}, under {}",
+                ratio, MIN_CONTRAST
+            )))
+        }
+
+        // stable, so each severity keeps the order above
+        result.sort_by_key(|issue| std::cmp::Reverse(issue.severity));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_first() {
+        let mut data = Collurgy {
+            foreground: [0.0; 3],
+            ..Default::default()
+        };
+        data.extras.insert(
+            "Test".to_string(),
+            HashMap::from([("PAST".to_string(), 16)]),
+        );
+        let issues = data.validate(&HashMap::new());
+        assert!(issues.iter().any(|i| i.severity == Severity::Error));
+        assert!(issues.iter().any(|i| i.severity == Severity::Warning));
+        assert!(issues
+            .windows(2)
+            .all(|pair| pair[0].severity >= pair[1].severity));
+    }
+
+    #[test]
+    fn indices_past_15() {
+        let mut data = Collurgy {
+            accent: 16,
+            accents: vec![3, 20],
+            primary_order: [1, 1, 2, 3, 4, 5],
+            ..Default::default()
+        };
+        data.overrides.insert(16, "#000000".to_string());
+        data.overrides.insert(2, "nope".to_string());
+        let errors = data
+            .validate(&HashMap::new())
+            .into_iter()
+            .filter(|i| i.severity == Severity::Error)
+            .count();
+        assert_eq!(errors, 5);
+        // still exports rather than panicking
+        let exporter: Exporter =
+            toml::from_str("name = \"Acc\"\nformatter = \"{ACCHEX} {ACC3HEX}\"").unwrap();
+        let hex = data
+            .compute()
+            .map(|c| colcon::irgb_to_hex(colcon::srgb_to_irgb(c)));
+        assert_eq!(
+            exporter.export(&data).unwrap(),
+            format!("{} {}\n", hex[15], hex[15])
+        );
+    }
+}